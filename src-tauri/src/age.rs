@@ -1,5 +1,9 @@
+use crate::fs_util::{commit_temp_file, temp_path_for, write_private_file};
+use crate::secure_delete::DeletionReport;
+use crate::settings::{load_settings, AgeBackend};
+use crate::verify::Verification;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
 use tokio::process::Command;
 
@@ -18,6 +22,14 @@ pub struct EncryptionResult {
     pub input_file: String,
     pub output_file: String,
     pub public_keys: Vec<String>,
+    #[serde(default)]
+    pub warnings: Vec<String>,
+    /// Set when verification was requested
+    #[serde(default)]
+    pub verification: Option<Verification>,
+    /// Set when the original was removed after encryption
+    #[serde(default)]
+    pub original_deletion: Option<DeletionReport>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -29,13 +41,30 @@ pub struct DecryptionResult {
     pub identity: String,
 }
 
-/// Get the path to a bundled executable based on the runtime OS and filename
-fn get_bundled_exe_path(exe_name: &str) -> Result<PathBuf, String> {
+/// Get the directory containing the running executable
+pub fn get_app_dir() -> Result<PathBuf, String> {
     let exe_path =
         std::env::current_exe().map_err(|e| format!("Could not determine app path: {}", e))?;
-    let parent = exe_path
+    exe_path
         .parent()
-        .ok_or("Could not determine app directory")?;
+        .map(|p| p.to_path_buf())
+        .ok_or("Could not determine app directory".to_string())
+}
+
+/// Path of the `age`/`age-keygen` executable selected in the settings
+pub(crate) fn get_age_exe_path(exe_name: &str) -> Result<PathBuf, String> {
+    let use_system = load_settings()
+        .map(|settings| settings.backend == AgeBackend::System)
+        .unwrap_or(false);
+    if use_system {
+        return Ok(PathBuf::from(exe_name));
+    }
+    get_bundled_exe_path(exe_name)
+}
+
+/// Get the path to a bundled executable based on the runtime OS and filename
+fn get_bundled_exe_path(exe_name: &str) -> Result<PathBuf, String> {
+    let parent = get_app_dir()?;
 
     match std::env::consts::OS {
         "windows" => {
@@ -98,7 +127,7 @@ fn get_bundled_exe_path(exe_name: &str) -> Result<PathBuf, String> {
 }
 
 pub async fn generate_keypair(comment: Option<&str>) -> Result<AgeKeyPair, String> {
-    let exe_path = get_bundled_exe_path("age-keygen")?;
+    let exe_path = get_age_exe_path("age-keygen")?;
     let mut cmd = Command::new(&exe_path);
 
     if let Some(comment) = comment {
@@ -149,27 +178,78 @@ fn parse_age_keygen_output(output: &str) -> Result<AgeKeyPair, String> {
     })
 }
 
+/// Path that stands for standard input or output, as in the age CLI
+pub const STDIO_PATH: &str = "-";
+
+/// Point age at the input and output files, or at our own stdin/stdout for `-`
+fn set_input_output(cmd: &mut Command, input: &str, output: &str) {
+    if output == STDIO_PATH {
+        cmd.stdout(Stdio::inherit());
+    } else {
+        cmd.arg("-o").arg(output).stdout(Stdio::piped());
+    }
+
+    if input == STDIO_PATH {
+        cmd.stdin(Stdio::inherit());
+    } else {
+        cmd.arg(input);
+    }
+
+    cmd.stderr(Stdio::piped());
+}
+
+/// Hidden temporary file age writes to instead of `output`, so that a failed
+/// or interrupted run never leaves a partial file at the destination
+fn temp_output_for(output: &str) -> Option<PathBuf> {
+    (output != STDIO_PATH).then(|| temp_path_for(Path::new(output)))
+}
+
+fn temp_output_target(temp_output: &Option<PathBuf>, output: &str) -> String {
+    temp_output
+        .as_ref()
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_else(|| output.to_string())
+}
+
+/// Move the temporary output into place once age succeeded, or remove it
+fn finish_output(temp_output: Option<PathBuf>, output: &str, succeeded: bool) -> Result<(), String> {
+    let Some(temp_output) = temp_output else {
+        return Ok(());
+    };
+
+    if succeeded {
+        if let Err(e) = commit_temp_file(&temp_output, Path::new(output)) {
+            let _ = std::fs::remove_file(&temp_output);
+            return Err(format!("Failed to write {}: {}", output, e));
+        }
+    } else {
+        let _ = std::fs::remove_file(&temp_output);
+    }
+
+    Ok(())
+}
+
 pub async fn encrypt_file(input: &str, output: &str, recipients: &[String], use_armor: bool) -> Result<(), String> {
-    let exe_path = get_bundled_exe_path("age")?;
+    let exe_path = get_age_exe_path("age")?;
     let mut cmd = Command::new(&exe_path);
     
     // Add armor flag if requested
     if use_armor {
         cmd.arg("--armor");
     }
-    
-    cmd.arg("-o").arg(output);
 
     for recipient in recipients {
         cmd.arg("-r").arg(recipient);
     }
 
-    cmd.arg(input).stdout(Stdio::piped()).stderr(Stdio::piped());
+    let temp_output = temp_output_for(output);
+    set_input_output(&mut cmd, input, &temp_output_target(&temp_output, output));
 
-    let output = cmd
-        .output()
-        .await
-        .map_err(|e| format!("Failed to execute age encrypt: {}", e))?;
+    let result = cmd.output().await;
+    let succeeded = result.as_ref().is_ok_and(|o| o.status.success());
+    finish_output(temp_output, output, succeeded)?;
+
+    let output = result.map_err(|e| format!("Failed to execute age encrypt: {}", e))?;
 
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
@@ -180,11 +260,6 @@ pub async fn encrypt_file(input: &str, output: &str, recipients: &[String], use_
 }
 
 pub async fn decrypt_file(input: &str, output: &str, identity: &str) -> Result<(), String> {
-    // Write identity to temporary file or pass via stdin
-    // For simplicity, write to a temp file first
-    use std::fs::File;
-    use std::io::Write;
-
     // Validate identity format: should be either:
     // - Age key: starts with "AGE-SECRET-KEY-"
     // - SSH key: starts with "-----BEGIN" or "ssh-" (for OpenSSH format)
@@ -198,39 +273,28 @@ pub async fn decrypt_file(input: &str, output: &str, identity: &str) -> Result<(
         );
     }
 
-    let temp_file = format!("{}.identity", input);
-    let mut file = File::create(&temp_file)
+    // age only reads identities from files; keep the file short-lived and private
+    let temp_file = temp_path_for(&std::env::temp_dir().join("identity"));
+    write_private_file(&temp_file, format!("{}\n", trimmed_identity).as_bytes())
         .map_err(|e| format!("Failed to create temp identity file: {}", e))?;
 
-    // Write identity with proper newline at end to ensure valid format
-    file.write_all(trimmed_identity.as_bytes())
-        .map_err(|e| format!("Failed to write identity to temp file: {}", e))?;
-
-    // Ensure file ends with newline (required by age for proper parsing)
-    if !trimmed_identity.ends_with('\n') {
-        file.write_all(b"\n")
-            .map_err(|e| format!("Failed to write newline to temp file: {}", e))?;
-    }
-
-    let exe_path = get_bundled_exe_path("age")?;
+    let exe_path = get_age_exe_path("age")?;
     let mut cmd = Command::new(&exe_path);
-    cmd.arg("-d")
-        .arg("-i")
-        .arg(&temp_file)
-        .arg("-o")
-        .arg(output)
-        .arg(input)
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped());
+    cmd.arg("-d").arg("-i").arg(&temp_file);
+    let temp_output = temp_output_for(output);
+    set_input_output(&mut cmd, input, &temp_output_target(&temp_output, output));
 
-    let output = cmd
-        .output()
-        .await
-        .map_err(|e| format!("Failed to execute age decrypt: {}", e))?;
+    let result = cmd.output().await;
 
     // Clean up temp file
     let _ = std::fs::remove_file(&temp_file);
 
+    // Plaintext only appears at the destination once age has authenticated all of it
+    let succeeded = result.as_ref().is_ok_and(|o| o.status.success());
+    finish_output(temp_output, output, succeeded)?;
+
+    let output = result.map_err(|e| format!("Failed to execute age decrypt: {}", e))?;
+
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
         return Err(format!(
@@ -256,7 +320,7 @@ pub async fn derive_public_from_ssh(ssh_pubkey: &str) -> Result<String, String>
     file.write_all(ssh_pubkey.as_bytes())
         .map_err(|e| format!("Failed to write SSH key to temp file: {}", e))?;
 
-    let exe_path = get_bundled_exe_path("age-keygen")?;
+    let exe_path = get_age_exe_path("age-keygen")?;
     let mut cmd = Command::new(&exe_path);
     cmd.arg("-y")
         .arg(temp_file)
@@ -0,0 +1,283 @@
+use crate::age::get_age_exe_path;
+use crate::fs_util::{temp_path_for, write_private_file};
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+
+/// First line of every binary age file
+pub const AGE_HEADER_MAGIC: &[u8] = b"age-encryption.org/v1";
+
+/// First line of an ASCII-armored age file
+pub const AGE_ARMOR_BEGIN: &str = "-----BEGIN AGE ENCRYPTED FILE-----";
+const AGE_ARMOR_END: &str = "-----END AGE ENCRYPTED FILE-----";
+
+/// Armored lines decoded when reading a header, enough for thousands of recipients
+const ARMOR_HEADER_LINES: usize = 4096;
+
+/// Whether data is a binary age file
+pub fn is_age_data(data: &[u8]) -> bool {
+    data.starts_with(AGE_HEADER_MAGIC)
+}
+
+/// Whether data is an ASCII-armored age file
+pub fn is_armored_age_data(data: &[u8]) -> bool {
+    data.trim_ascii_start().starts_with(AGE_ARMOR_BEGIN.as_bytes())
+}
+
+/// Recipient stanza of an age header, e.g. `X25519`, `scrypt` or `ssh-ed25519`
+/// with the key tag as its first argument
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct AgeStanza {
+    pub kind: String,
+    pub args: Vec<String>,
+}
+
+/// What the header of an age file tells without decrypting it
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct AgeHeaderInfo {
+    pub armored: bool,
+    pub stanzas: Vec<AgeStanza>,
+}
+
+impl AgeHeaderInfo {
+    /// Whether the file is encrypted with a passphrase rather than to recipients
+    pub fn is_passphrase_encrypted(&self) -> bool {
+        self.stanzas.iter().any(|s| s.kind == "scrypt")
+    }
+}
+
+/// Parse the recipient stanzas of a binary or armored age file. Returns None
+/// when the data is not an age file or the header is incomplete.
+pub fn parse_age_header(data: &[u8]) -> Option<AgeHeaderInfo> {
+    if is_age_data(data) {
+        return Some(AgeHeaderInfo {
+            armored: false,
+            stanzas: parse_header_stanzas(data)?,
+        });
+    }
+
+    if is_armored_age_data(data) {
+        use base64::{engine::general_purpose::STANDARD, Engine as _};
+
+        let text = String::from_utf8_lossy(data);
+        let body: String = text
+            .lines()
+            .map(str::trim)
+            .skip_while(|line| *line != AGE_ARMOR_BEGIN)
+            .skip(1)
+            .take_while(|line| *line != AGE_ARMOR_END)
+            .take(ARMOR_HEADER_LINES)
+            .collect();
+        // Lines hold 64 characters, so any prefix of whole lines is valid base64
+        let decoded = STANDARD.decode(&body).ok()?;
+        return Some(AgeHeaderInfo {
+            armored: true,
+            stanzas: parse_header_stanzas(&decoded)?,
+        });
+    }
+
+    None
+}
+
+fn parse_header_stanzas(data: &[u8]) -> Option<Vec<AgeStanza>> {
+    let mut stanzas = Vec::new();
+
+    for line in data.split(|b| *b == b'\n').skip(1) {
+        let line = std::str::from_utf8(line).ok()?;
+        if line.starts_with("---") {
+            return Some(stanzas);
+        }
+        if let Some(stanza) = line.strip_prefix("-> ") {
+            let mut parts = stanza.split(' ').map(str::to_string);
+            let kind = parts.next()?;
+            // age adds random "grease" stanzas that no identity matches
+            if kind.ends_with("-grease") {
+                continue;
+            }
+            stanzas.push(AgeStanza {
+                kind,
+                args: parts.collect(),
+            });
+        }
+    }
+
+    None
+}
+
+fn is_ssh_recipient(recipient: &str) -> bool {
+    recipient.starts_with("ssh-ed25519 ") || recipient.starts_with("ssh-rsa ")
+}
+
+/// Encrypt data as a standard age file to X25519 or SSH recipients.
+/// X25519 recipients are handled in-process, SSH recipients through the
+/// bundled `age` binary.
+pub fn encrypt_to_recipients(recipients: &[String], data: &[u8]) -> Result<Vec<u8>, String> {
+    let recipients: Vec<&str> = recipients.iter().map(|r| r.trim()).filter(|r| !r.is_empty()).collect();
+    if recipients.is_empty() {
+        return Err("At least one recipient is required".to_string());
+    }
+
+    if recipients.iter().any(|r| is_ssh_recipient(r)) {
+        return encrypt_with_cli(&recipients, data);
+    }
+
+    let mut parsed: Vec<Box<dyn age::Recipient + Send>> = Vec::new();
+    for recipient in recipients {
+        let recipient = age::x25519::Recipient::from_str(recipient)
+            .map_err(|e| format!("Invalid age recipient {}: {}", recipient, e))?;
+        parsed.push(Box::new(recipient));
+    }
+
+    let encryptor = age::Encryptor::with_recipients(parsed).ok_or("At least one recipient is required")?;
+    let mut encrypted = Vec::new();
+    let mut writer = encryptor
+        .wrap_output(&mut encrypted)
+        .map_err(|e| format!("Encryption failed: {}", e))?;
+    writer
+        .write_all(data)
+        .map_err(|e| format!("Encryption failed: {}", e))?;
+    writer
+        .finish()
+        .map_err(|e| format!("Encryption failed: {}", e))?;
+
+    Ok(encrypted)
+}
+
+/// Why an age file could not be decrypted
+#[derive(Debug)]
+pub enum AgeDecryptFailure {
+    /// The header is missing, truncated or malformed
+    InvalidHeader(String),
+    /// The passphrase or identities did not unlock the file key, or the
+    /// header was modified
+    Unlock(String),
+    /// The header verified but the payload is truncated or was modified
+    Payload(String),
+}
+
+impl std::fmt::Display for AgeDecryptFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AgeDecryptFailure::InvalidHeader(e) => write!(f, "Invalid age file: {}", e),
+            AgeDecryptFailure::Unlock(e) => write!(
+                f,
+                "Decryption failed - incorrect passphrase, no matching identity or corrupted header: {}",
+                e
+            ),
+            AgeDecryptFailure::Payload(e) => write!(f, "Decryption failed - corrupted data: {}", e),
+        }
+    }
+}
+
+impl From<age::DecryptError> for AgeDecryptFailure {
+    fn from(e: age::DecryptError) -> Self {
+        match e {
+            age::DecryptError::InvalidHeader | age::DecryptError::UnknownFormat => {
+                AgeDecryptFailure::InvalidHeader(e.to_string())
+            }
+            age::DecryptError::Io(ref io) if io.kind() == std::io::ErrorKind::UnexpectedEof => {
+                AgeDecryptFailure::InvalidHeader(e.to_string())
+            }
+            _ => AgeDecryptFailure::Unlock(e.to_string()),
+        }
+    }
+}
+
+/// Decrypt an age file with either a passphrase (for scrypt-encrypted files)
+/// or an identity: one or more `AGE-SECRET-KEY-1...` lines, or an unencrypted
+/// SSH private key, which is handed to the bundled `age` binary.
+pub fn decrypt_age_data(secret: &str, data: &[u8]) -> Result<Vec<u8>, String> {
+    try_decrypt_age_data(secret, data).map_err(|e| e.to_string())
+}
+
+/// Like `decrypt_age_data`, but tells apart which part of the file failed
+pub fn try_decrypt_age_data(secret: &str, data: &[u8]) -> Result<Vec<u8>, AgeDecryptFailure> {
+    let decryptor = age::Decryptor::new(data)?;
+
+    let mut reader = match decryptor {
+        age::Decryptor::Passphrase(decryptor) => {
+            decryptor.decrypt(&age::secrecy::Secret::new(secret.to_owned()), None)?
+        }
+        age::Decryptor::Recipients(decryptor) => {
+            let secret = secret.trim();
+            if secret.starts_with("-----BEGIN") {
+                return decrypt_with_cli(secret, data).map_err(AgeDecryptFailure::Unlock);
+            }
+
+            let identities = secret
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(|line| {
+                    age::x25519::Identity::from_str(line)
+                        .map_err(|e| AgeDecryptFailure::Unlock(format!("Invalid age identity: {}", e)))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            if identities.is_empty() {
+                return Err(AgeDecryptFailure::Unlock(
+                    "An age identity is required to decrypt this file".to_string(),
+                ));
+            }
+
+            decryptor.decrypt(identities.iter().map(|i| i as &dyn age::Identity))?
+        }
+    };
+
+    let mut decrypted = Vec::new();
+    reader
+        .read_to_end(&mut decrypted)
+        .map_err(|e| AgeDecryptFailure::Payload(e.to_string()))?;
+
+    Ok(decrypted)
+}
+
+/// Run the bundled `age` binary with data on stdin and return its stdout
+fn run_age_cli(cmd: &mut Command, data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut child = cmd
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to execute age: {}", e))?;
+
+    // Feed stdin from a separate thread so a full stdout pipe cannot deadlock us
+    let mut stdin = child.stdin.take().ok_or("Failed to open age stdin")?;
+    let input = data.to_vec();
+    let writer = std::thread::spawn(move || stdin.write_all(&input));
+
+    let output = child
+        .wait_with_output()
+        .map_err(|e| format!("Failed to execute age: {}", e))?;
+    let _ = writer.join();
+
+    if !output.status.success() {
+        return Err(format!("age failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+
+    Ok(output.stdout)
+}
+
+fn encrypt_with_cli(recipients: &[&str], data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut cmd = Command::new(get_age_exe_path("age")?);
+    for recipient in recipients {
+        cmd.arg("-r").arg(recipient);
+    }
+    run_age_cli(&mut cmd, data)
+}
+
+fn decrypt_with_cli(identity: &str, data: &[u8]) -> Result<Vec<u8>, String> {
+    // age only reads identities from files; keep the file short-lived and private
+    let identity_path = temp_path_for(&std::env::temp_dir().join("identity"));
+    write_private_file(&identity_path, format!("{}\n", identity.trim()).as_bytes())
+        .map_err(|e| format!("Failed to create temp identity file: {}", e))?;
+
+    let mut cmd = Command::new(get_age_exe_path("age")?);
+    cmd.arg("-d").arg("-i").arg(&identity_path);
+    let result = run_age_cli(&mut cmd, data);
+
+    let _ = std::fs::remove_file(&identity_path);
+    result
+}
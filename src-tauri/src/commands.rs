@@ -1,12 +1,187 @@
 use crate::age::{AgeKeyPair, EncryptionResult, DecryptionResult, generate_keypair, encrypt_file, decrypt_file, derive_public_from_ssh};
-use crate::key_storage::{StoredKey, create_stored_key, save_key_storage, load_key_storage, key_storage_exists, get_default_key_storage_path, get_or_create_passphrase, export_keys_to_file, import_keys_from_file};
+use crate::key_storage::{StoredKey, create_stored_key, load_key_storage, key_storage_exists, get_default_key_storage_path, get_profile_key_storage_path, get_or_create_passphrase, export_keys_to_file, import_keys_from_file, record_key_usage, merge_usage_metadata, KeyStorage, STORAGE_VERSION, is_portable_mode, get_app_config_dir, commit_key_storage, KeyStorageBackup, list_key_storage_backups, restore_key_storage_backup, ExportOptions, ExportPreview, preview_export_file, StorageEncryption, reencrypt_key_storage};
+use crate::key_storage_check::{KeyStorageReport, StorageDiagnosis, check_key_storage, recover_key_storage_from_backup, salvage_keys};
+use crate::settings::{Settings, OverwritePolicy, load_settings, save_settings, reset_settings, SETTINGS_CHANGED_EVENT};
+use crate::secure_delete::secure_delete;
+use crate::verify::{Verification, VerifyMethod, verify_encryption};
+use crate::file_detection::{FileKind, detect_file_kind, is_hidden, matches_pattern};
+use crate::open_files::{OpenFilesRequest, PendingOpenFiles};
+use crate::output_paths::{FileOperation, default_output_name, default_output_path, resolve_output_path};
+use crate::envelope::{EnvelopeDecryptionResult, decrypt_envelope, encrypt_envelope, random_envelope_name, restored_file_name};
+use crate::key_files::{import_age_key_files, export_identity_file, export_recipients_file, export_public_key_bundle};
+use crate::ssh_keys::{SshKeyCandidate, get_default_ssh_directory, scan_ssh_directory, import_ssh_keys};
+use crate::import_plan::{ImportPlan, ImportResolution, ImportResult, plan_key_import, apply_key_import};
+use crate::profiles::{Profile, PassphraseMode, load_profiles, get_active_profile, create_profile, rename_profile, set_profile_passphrase_mode, switch_profile, delete_profile};
+use crate::contacts::{Contact, RecipientGroup, create_contact, create_recipient_group, upsert_contact, remove_contact, upsert_recipient_group, remove_recipient_group, expand_recipient_groups, add_contact_key, accept_contact_key, reject_contact_key, prepare_recipients, ContactKeyChange};
+use std::path::{Path, PathBuf};
 use std::sync::Mutex;
+use tauri::{Emitter, Manager};
 
-// For simplicity, we'll store keys in memory for now
-// Later we'll implement encrypted persistent storage
-#[allow(dead_code)]
+/// Key storage as last loaded or saved by the frontend, kept in memory so
+/// encrypt/decrypt operations can update key usage metadata on disk
+pub struct LoadedKeyStorage {
+    pub passphrase: String,
+    pub file_path: String,
+    pub storage: KeyStorage,
+}
+
+#[derive(Default)]
 pub struct KeyStore {
-    pub keys: Mutex<Vec<crate::age::AgeKeyPair>>,
+    pub loaded: Mutex<Option<LoadedKeyStorage>>,
+}
+
+impl KeyStore {
+    fn remember(&self, passphrase: &str, file_path: &str, storage: KeyStorage) {
+        if let Ok(mut loaded) = self.loaded.lock() {
+            *loaded = Some(LoadedKeyStorage {
+                passphrase: passphrase.to_string(),
+                file_path: file_path.to_string(),
+                storage,
+            });
+        }
+    }
+
+    /// Passphrase and copy of the loaded key storage if it was loaded from the given path
+    fn loaded_storage(&self, file_path: &str) -> Option<(String, KeyStorage)> {
+        self.loaded.lock().ok().and_then(|loaded| {
+            loaded
+                .as_ref()
+                .filter(|loaded| loaded.file_path == file_path)
+                .map(|loaded| (loaded.passphrase.clone(), loaded.storage.clone()))
+        })
+    }
+
+    /// Run a read-only operation against the loaded key storage
+    fn with_storage<T, F>(&self, f: F) -> Result<T, String>
+    where
+        F: FnOnce(&KeyStorage) -> Result<T, String>,
+    {
+        let loaded = self
+            .loaded
+            .lock()
+            .map_err(|e| format!("Key store lock poisoned: {}", e))?;
+        let loaded = loaded.as_ref().ok_or("Key storage is not loaded")?;
+        f(&loaded.storage)
+    }
+
+    /// Modify the loaded key storage and persist it to disk
+    fn update_storage<T, F>(&self, f: F) -> Result<T, String>
+    where
+        F: FnOnce(&mut KeyStorage) -> Result<T, String>,
+    {
+        let mut loaded = self
+            .loaded
+            .lock()
+            .map_err(|e| format!("Key store lock poisoned: {}", e))?;
+        let loaded = loaded.as_mut().ok_or("Key storage is not loaded")?;
+
+        let mut storage = loaded.storage.clone();
+        let result = f(&mut storage)?;
+        commit_key_storage(
+            &loaded.passphrase,
+            &mut storage,
+            &loaded.file_path,
+            Some(loaded.storage.generation),
+            &loaded.passphrase,
+            true,
+        )?;
+        loaded.storage = storage;
+
+        Ok(result)
+    }
+
+    /// Re-encrypt the loaded key storage in another format. `secret` becomes
+    /// the passphrase or identity that unlocks the store from now on.
+    fn change_encryption(&self, encryption: StorageEncryption, secret: &str) -> Result<(), String> {
+        let mut loaded = self
+            .loaded
+            .lock()
+            .map_err(|e| format!("Key store lock poisoned: {}", e))?;
+        let loaded = loaded.as_mut().ok_or("Key storage is not loaded")?;
+
+        let mut storage = reencrypt_key_storage(&loaded.storage, encryption, secret)?;
+        commit_key_storage(
+            secret,
+            &mut storage,
+            &loaded.file_path,
+            Some(loaded.storage.generation),
+            &loaded.passphrase,
+            true,
+        )?;
+        loaded.passphrase = secret.to_string();
+        loaded.storage = storage;
+
+        Ok(())
+    }
+
+    /// Expand recipient groups, add the self recipients and check the
+    /// recipients against the loaded key storage. Returns warnings about them.
+    fn prepare_recipients(
+        &self,
+        settings: &Settings,
+        recipients: &mut Vec<String>,
+        recipient_groups: Option<Vec<String>>,
+    ) -> Result<Vec<String>, String> {
+        let loaded = self
+            .loaded
+            .lock()
+            .map_err(|e| format!("Key store lock poisoned: {}", e))?;
+        prepare_recipients(
+            loaded.as_ref().map(|loaded| &loaded.storage),
+            settings,
+            recipients,
+            &recipient_groups.unwrap_or_default(),
+        )
+    }
+
+    /// Mark matching keys as used and persist the updated key storage
+    fn record_usage<F>(&self, matches: F) -> Result<(), String>
+    where
+        F: Fn(&StoredKey) -> bool,
+    {
+        let mut loaded = self
+            .loaded
+            .lock()
+            .map_err(|e| format!("Key store lock poisoned: {}", e))?;
+
+        if let Some(loaded) = loaded.as_mut() {
+            let mut storage = loaded.storage.clone();
+            if record_key_usage(&mut storage.keys, matches) {
+                commit_key_storage(
+                    &loaded.passphrase,
+                    &mut storage,
+                    &loaded.file_path,
+                    Some(loaded.storage.generation),
+                    &loaded.passphrase,
+                    false,
+                )?;
+                loaded.storage = storage;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Run blocking file or process work off the async runtime's worker threads
+async fn run_blocking<T, F>(f: F) -> Result<T, String>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    tauri::async_runtime::spawn_blocking(f)
+        .await
+        .map_err(|e| format!("Background task failed: {}", e))
+}
+
+/// Record key usage on a blocking thread. Saving re-encrypts the whole key
+/// storage, which takes seconds with scrypt.
+async fn record_usage_in_background<F>(app: &tauri::AppHandle, matches: F) -> Result<(), String>
+where
+    F: Fn(&StoredKey) -> bool + Send + 'static,
+{
+    let app = app.clone();
+    run_blocking(move || app.state::<KeyStore>().record_usage(matches)).await?
 }
 
 #[tauri::command]
@@ -15,30 +190,110 @@ pub async fn generate_age_keys(comment: Option<String>) -> Result<AgeKeyPair, St
 }
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn encrypt_file_cmd(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, KeyStore>,
     input_file: String,
     output_file: String,
-    recipients: Vec<String>,
-    use_armor: bool
+    mut recipients: Vec<String>,
+    recipient_groups: Option<Vec<String>>,
+    use_armor: Option<bool>,
+    overwrite_policy: Option<OverwritePolicy>,
+    remove_original: Option<bool>,
+    verify: Option<VerifyMethod>
 ) -> Result<EncryptionResult, String> {
+    let settings = load_settings()?;
+    let use_armor = use_armor.unwrap_or(settings.default_armor);
+    let output_file = resolve_output_path(
+        Path::new(&input_file),
+        Path::new(&output_file),
+        overwrite_policy.unwrap_or(settings.overwrite_policy),
+    )?
+    .to_string_lossy()
+    .to_string();
+
+    let mut warnings = state.prepare_recipients(&settings, &mut recipients, recipient_groups)?;
+
     encrypt_file(&input_file, &output_file, &recipients, use_armor).await?;
 
+    let used = recipients.clone();
+    if let Err(e) = record_usage_in_background(&app, move |key| used.iter().any(|r| r.trim() == key.public_key)).await {
+        warnings.push(format!("Failed to update key usage: {}", e));
+    }
+
+    // Removing the original requires the output to have been read back in full
+    let remove_original = remove_original.unwrap_or(false);
+    let verify = if remove_original { Some(VerifyMethod::Decrypt) } else { verify };
+    let verification = match verify {
+        Some(method) => {
+            // Only decrypting needs the stored identities
+            let keys = match method {
+                VerifyMethod::Decrypt => state.with_storage(|storage| Ok(storage.keys.clone())),
+                VerifyMethod::Header { .. } => Ok(Vec::new()),
+            };
+            Some(match keys {
+                Ok(keys) => {
+                    let (output, input, checked) = (output_file.clone(), input_file.clone(), recipients.clone());
+                    run_blocking(move || verify_encryption(&output, &input, &method, &keys, &checked))
+                        .await
+                        .unwrap_or_else(|reason| Verification::Inconclusive { reason })
+                }
+                Err(reason) => Verification::Inconclusive { reason },
+            })
+        }
+        None => None,
+    };
+    if let Some(reason) = verification.as_ref().and_then(Verification::reason) {
+        warnings.push(format!("The output could not be verified: {}", reason));
+    }
+
+    let mut original_deletion = None;
+    if remove_original {
+        if verification == Some(Verification::Decrypted) {
+            let original = input_file.clone();
+            match run_blocking(move || secure_delete(Path::new(&original))).await.and_then(|result| result) {
+                Ok(report) => original_deletion = Some(report),
+                Err(e) => warnings.push(format!("The original file was kept: {}", e)),
+            }
+        } else {
+            warnings.push("The original file was kept because the output was not verified".to_string());
+        }
+    }
+
     Ok(EncryptionResult {
-        success: true,
+        success: !matches!(verification, Some(Verification::Failed { .. })),
         input_file,
         output_file,
         public_keys: recipients,
+        warnings,
+        verification,
+        original_deletion,
     })
 }
 
 #[tauri::command]
 pub async fn decrypt_file_cmd(
+    app: tauri::AppHandle,
     input_file: String,
     output_file: String,
-    identity: String
+    identity: String,
+    overwrite_policy: Option<OverwritePolicy>
 ) -> Result<DecryptionResult, String> {
+    let policy = match overwrite_policy {
+        Some(policy) => policy,
+        None => load_settings()?.overwrite_policy,
+    };
+    let output_file = resolve_output_path(Path::new(&input_file), Path::new(&output_file), policy)?
+        .to_string_lossy()
+        .to_string();
+
     decrypt_file(&input_file, &output_file, &identity).await?;
 
+    // Usage tracking is best effort, the file has already been decrypted
+    let used = identity.clone();
+    let _ = record_usage_in_background(&app, move |key| key.private_key.as_deref().map(str::trim) == Some(used.trim())).await;
+
     Ok(DecryptionResult {
         success: true,
         input_file,
@@ -47,6 +302,119 @@ pub async fn decrypt_file_cmd(
     })
 }
 
+/// Output path the frontend should propose for a file, following the naming
+/// rules and the output directory and overwrite policies of the settings
+#[tauri::command]
+pub fn suggest_output_path_cmd(
+    input_file: String,
+    operation: FileOperation,
+    use_armor: Option<bool>
+) -> Result<String, String> {
+    let settings = load_settings()?;
+    let input = Path::new(&input_file);
+    let output = default_output_path(input, operation, use_armor.unwrap_or(settings.default_armor), &settings);
+
+    // Propose a free name unless existing files are overwritten anyway
+    let policy = match settings.overwrite_policy {
+        OverwritePolicy::Overwrite => OverwritePolicy::Overwrite,
+        OverwritePolicy::Refuse | OverwritePolicy::AutoSuffix => OverwritePolicy::AutoSuffix,
+    };
+    Ok(resolve_output_path(input, &output, policy)?.to_string_lossy().to_string())
+}
+
+/// Directory an envelope is written to: the given one, or the directory the
+/// output policy of the settings picks for the input
+fn envelope_directory(input: &Path, output_directory: Option<String>, operation: FileOperation, settings: &Settings) -> PathBuf {
+    match output_directory.filter(|dir| !dir.trim().is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => default_output_path(input, operation, false, settings)
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default(),
+    }
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn encrypt_envelope_cmd(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, KeyStore>,
+    input_file: String,
+    output_directory: Option<String>,
+    mut recipients: Vec<String>,
+    recipient_groups: Option<Vec<String>>,
+    use_armor: Option<bool>,
+    hide_name: Option<bool>,
+    overwrite_policy: Option<OverwritePolicy>
+) -> Result<EncryptionResult, String> {
+    let settings = load_settings()?;
+    let use_armor = use_armor.unwrap_or(settings.default_armor);
+    let input = Path::new(&input_file);
+
+    let name = if hide_name.unwrap_or(true) {
+        random_envelope_name(use_armor)
+    } else {
+        let input_name = input.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        default_output_name(&input_name, FileOperation::Encrypt, use_armor)
+    };
+    let output = envelope_directory(input, output_directory, FileOperation::Encrypt, &settings).join(name);
+    let output = resolve_output_path(input, &output, overwrite_policy.unwrap_or(settings.overwrite_policy))?;
+
+    let mut warnings = state.prepare_recipients(&settings, &mut recipients, recipient_groups)?;
+
+    let (envelope_input, envelope_output, envelope_recipients) = (input.to_path_buf(), output.clone(), recipients.clone());
+    run_blocking(move || encrypt_envelope(&envelope_input, &envelope_output, &envelope_recipients, use_armor)).await??;
+
+    let used = recipients.clone();
+    if let Err(e) = record_usage_in_background(&app, move |key| used.iter().any(|r| r.trim() == key.public_key)).await {
+        warnings.push(format!("Failed to update key usage: {}", e));
+    }
+
+    Ok(EncryptionResult {
+        success: true,
+        input_file,
+        output_file: output.to_string_lossy().to_string(),
+        public_keys: recipients,
+        warnings,
+        verification: None,
+        original_deletion: None,
+    })
+}
+
+#[tauri::command]
+pub async fn decrypt_envelope_cmd(
+    app: tauri::AppHandle,
+    input_file: String,
+    output_directory: Option<String>,
+    identity: String,
+    overwrite_policy: Option<OverwritePolicy>
+) -> Result<EnvelopeDecryptionResult, String> {
+    let settings = load_settings()?;
+    let input = Path::new(&input_file);
+    let directory = envelope_directory(input, output_directory, FileOperation::Decrypt, &settings);
+    let policy = overwrite_policy.unwrap_or(settings.overwrite_policy);
+
+    let (envelope_input, envelope_identity) = (input.to_path_buf(), identity.clone());
+    let (output, metadata, warnings) = run_blocking(move || {
+        decrypt_envelope(&envelope_input, &envelope_identity, |metadata| {
+            resolve_output_path(&envelope_input, &directory.join(restored_file_name(metadata)), policy)
+        })
+    })
+    .await??;
+
+    // Usage tracking is best effort, the file has already been decrypted
+    let used = identity.clone();
+    let _ = record_usage_in_background(&app, move |key| key.private_key.as_deref().map(str::trim) == Some(used.trim())).await;
+
+    Ok(EnvelopeDecryptionResult {
+        success: true,
+        input_file,
+        output_file: output.to_string_lossy().to_string(),
+        metadata,
+        warnings,
+    })
+}
+
 #[tauri::command]
 pub async fn derive_public_key_from_ssh(ssh_pubkey: String) -> Result<String, String> {
     derive_public_from_ssh(&ssh_pubkey).await
@@ -73,20 +441,118 @@ pub fn key_storage_exists_cmd(file_path: Option<String>) -> Result<bool, String>
 }
 
 #[tauri::command]
-pub fn load_key_storage_cmd(passphrase: String, file_path: Option<String>) -> Result<Vec<StoredKey>, String> {
+pub fn load_key_storage_cmd(
+    state: tauri::State<'_, KeyStore>,
+    passphrase: String,
+    file_path: Option<String>
+) -> Result<Vec<StoredKey>, String> {
     let path = file_path.unwrap_or_else(|| get_default_key_storage_path().unwrap_or_default());
-    load_key_storage(&passphrase, &path)
+    let storage = load_key_storage(&passphrase, &path)?;
+    let keys = storage.keys.clone();
+    state.remember(&passphrase, &path, storage);
+    Ok(keys)
 }
 
 #[tauri::command]
 pub fn get_or_create_passphrase_cmd() -> Result<String, String> {
-    get_or_create_passphrase()
+    // Manual profiles never get a stored passphrase, the user has to enter it
+    match get_active_profile()?.passphrase_mode {
+        PassphraseMode::Auto => get_or_create_passphrase(),
+        PassphraseMode::Manual => Err("The key store of this profile needs a passphrase to be entered".to_string()),
+    }
 }
 
 #[tauri::command]
-pub fn save_key_storage_cmd(passphrase: String, keys: Vec<StoredKey>, file_path: Option<String>) -> Result<(), String> {
+pub fn save_key_storage_cmd(
+    state: tauri::State<'_, KeyStore>,
+    passphrase: String,
+    mut keys: Vec<StoredKey>,
+    file_path: Option<String>
+) -> Result<(), String> {
     let path = file_path.unwrap_or_else(|| get_default_key_storage_path().unwrap_or_default());
-    save_key_storage(&passphrase, &keys, &path)
+
+    // Keep contacts and groups, which the frontend does not send along with the keys
+    let (disk_passphrase, base) = match state.loaded_storage(&path) {
+        Some((loaded_passphrase, storage)) => (loaded_passphrase, Some(storage)),
+        // An existing store that cannot be loaded must not be replaced by the frontend keys alone
+        None if key_storage_exists(&path) => (passphrase.clone(), Some(load_key_storage(&passphrase, &path)?)),
+        None => (passphrase.clone(), None),
+    };
+    let expected_generation = base.as_ref().map(|storage| storage.generation);
+    let mut storage = base.unwrap_or_default();
+
+    // The frontend copy of the keys may predate usage recorded by encrypt/decrypt
+    merge_usage_metadata(&mut keys, &storage.keys);
+    storage.keys = keys;
+    storage.version = STORAGE_VERSION;
+
+    commit_key_storage(&passphrase, &mut storage, &path, expected_generation, &disk_passphrase, true)?;
+    state.remember(&passphrase, &path, storage);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn list_key_storage_backups_cmd(file_path: Option<String>) -> Result<Vec<KeyStorageBackup>, String> {
+    let path = file_path.unwrap_or_else(|| get_default_key_storage_path().unwrap_or_default());
+    list_key_storage_backups(&path)
+}
+
+#[tauri::command]
+pub fn restore_key_storage_backup_cmd(
+    state: tauri::State<'_, KeyStore>,
+    backup_path: String,
+    file_path: Option<String>
+) -> Result<(), String> {
+    let path = file_path.unwrap_or_else(|| get_default_key_storage_path().unwrap_or_default());
+    restore_key_storage_backup(&path, &backup_path)?;
+
+    // The frontend has to load the restored storage again
+    if let Ok(mut loaded) = state.loaded.lock() {
+        *loaded = None;
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn check_key_storage_cmd(secret: String, file_path: Option<String>) -> Result<KeyStorageReport, String> {
+    let path = file_path.unwrap_or_else(|| get_default_key_storage_path().unwrap_or_default());
+    check_key_storage(&secret, &path)
+}
+
+#[tauri::command]
+pub fn recover_key_storage_cmd(
+    state: tauri::State<'_, KeyStore>,
+    secret: String,
+    file_path: Option<String>
+) -> Result<StorageDiagnosis, String> {
+    let path = file_path.unwrap_or_else(|| get_default_key_storage_path().unwrap_or_default());
+    let restored = recover_key_storage_from_backup(&secret, &path)?;
+
+    // The frontend has to load the recovered storage again
+    if let Ok(mut loaded) = state.loaded.lock() {
+        *loaded = None;
+    }
+    Ok(restored)
+}
+
+#[tauri::command]
+pub fn salvage_key_storage_keys_cmd(secret: String, file_path: Option<String>) -> Result<Vec<StoredKey>, String> {
+    let path = file_path.unwrap_or_else(|| get_default_key_storage_path().unwrap_or_default());
+    salvage_keys(&secret, &path)
+}
+
+#[tauri::command]
+pub fn get_key_storage_encryption_cmd(state: tauri::State<'_, KeyStore>) -> Result<StorageEncryption, String> {
+    state.with_storage(|storage| Ok(storage.encryption.clone()))
+}
+
+#[tauri::command]
+pub fn set_key_storage_encryption_cmd(
+    state: tauri::State<'_, KeyStore>,
+    encryption: StorageEncryption,
+    secret: String
+) -> Result<(), String> {
+    state.change_encryption(encryption, &secret)
 }
 
 #[tauri::command]
@@ -94,14 +560,49 @@ pub fn create_stored_key_cmd(
     name: String,
     public_key: String,
     private_key: Option<String>,
-    comment: Option<String>
+    comment: Option<String>,
+    tags: Option<Vec<String>>,
+    expires_at: Option<u64>
 ) -> Result<StoredKey, String> {
-    Ok(create_stored_key(name, public_key, private_key, comment))
+    let mut key = create_stored_key(name, public_key, private_key, comment);
+    key.tags = tags.unwrap_or_default();
+    key.expires_at = expires_at;
+    Ok(key)
 }
 
 #[tauri::command]
-pub fn export_keys_cmd(passphrase: String, keys: Vec<StoredKey>, file_path: String) -> Result<(), String> {
-    export_keys_to_file(&passphrase, &keys, &file_path)
+pub fn export_keys_cmd(
+    passphrase: String,
+    keys: Vec<StoredKey>,
+    file_path: String,
+    options: Option<ExportOptions>
+) -> Result<(), String> {
+    export_keys_to_file(&passphrase, &keys, &file_path, &options.unwrap_or_default())
+}
+
+#[tauri::command]
+pub fn plan_key_import_cmd(existing: Vec<StoredKey>, incoming: Vec<StoredKey>) -> Result<ImportPlan, String> {
+    Ok(plan_key_import(&existing, &incoming))
+}
+
+#[tauri::command]
+pub fn apply_key_import_cmd(
+    existing: Vec<StoredKey>,
+    incoming: Vec<StoredKey>,
+    resolution: Option<ImportResolution>,
+    overrides: Option<std::collections::HashMap<String, ImportResolution>>
+) -> Result<ImportResult, String> {
+    Ok(apply_key_import(
+        &existing,
+        &incoming,
+        resolution.unwrap_or_default(),
+        &overrides.unwrap_or_default(),
+    ))
+}
+
+#[tauri::command]
+pub fn preview_export_file_cmd(file_path: String) -> Result<ExportPreview, String> {
+    preview_export_file(&file_path)
 }
 
 #[tauri::command]
@@ -109,6 +610,240 @@ pub fn import_keys_cmd(passphrase: String, file_path: String) -> Result<Vec<Stor
     import_keys_from_file(&passphrase, &file_path)
 }
 
+#[tauri::command]
+pub fn import_age_key_files_cmd(path: String) -> Result<Vec<StoredKey>, String> {
+    import_age_key_files(&path)
+}
+
+#[tauri::command]
+pub fn export_identity_file_cmd(keys: Vec<StoredKey>, file_path: String, passphrase: Option<String>) -> Result<(), String> {
+    export_identity_file(&keys, &file_path, passphrase.as_deref())
+}
+
+#[tauri::command]
+pub fn export_recipients_file_cmd(keys: Vec<StoredKey>, file_path: String) -> Result<(), String> {
+    export_recipients_file(&keys, &file_path)
+}
+
+#[tauri::command]
+pub fn export_public_key_bundle_cmd(keys: Vec<StoredKey>, file_path: String) -> Result<(), String> {
+    export_public_key_bundle(&keys, &file_path)
+}
+
+#[tauri::command]
+pub fn scan_ssh_keys_cmd(directory: Option<String>) -> Result<Vec<SshKeyCandidate>, String> {
+    let directory = match directory {
+        Some(directory) => std::path::PathBuf::from(directory),
+        None => get_default_ssh_directory()?,
+    };
+    scan_ssh_directory(&directory)
+}
+
+#[tauri::command]
+pub fn import_ssh_keys_cmd(candidates: Vec<SshKeyCandidate>, include_private: bool) -> Result<Vec<StoredKey>, String> {
+    import_ssh_keys(&candidates, include_private)
+}
+
+#[tauri::command]
+pub fn list_contacts_cmd(state: tauri::State<'_, KeyStore>) -> Result<Vec<Contact>, String> {
+    state.with_storage(|storage| Ok(storage.contacts.clone()))
+}
+
+#[tauri::command]
+pub fn create_contact_cmd(
+    state: tauri::State<'_, KeyStore>,
+    name: String,
+    email: Option<String>,
+    public_keys: Vec<String>
+) -> Result<Contact, String> {
+    let contact = create_contact(name, email, public_keys);
+    state.update_storage(|storage| {
+        upsert_contact(storage, contact.clone());
+        Ok(contact)
+    })
+}
+
+#[tauri::command]
+pub fn save_contact_cmd(state: tauri::State<'_, KeyStore>, contact: Contact) -> Result<(), String> {
+    state.update_storage(|storage| {
+        upsert_contact(storage, contact);
+        Ok(())
+    })
+}
+
+#[tauri::command]
+pub fn delete_contact_cmd(state: tauri::State<'_, KeyStore>, contact_id: String) -> Result<(), String> {
+    state.update_storage(|storage| remove_contact(storage, &contact_id))
+}
+
+#[tauri::command]
+pub fn add_contact_key_cmd(
+    state: tauri::State<'_, KeyStore>,
+    contact_id: String,
+    public_key: String,
+    label: Option<String>
+) -> Result<ContactKeyChange, String> {
+    state.update_storage(|storage| add_contact_key(storage, &contact_id, &public_key, label))
+}
+
+#[tauri::command]
+pub fn accept_contact_key_cmd(
+    state: tauri::State<'_, KeyStore>,
+    contact_id: String,
+    fingerprint: String,
+    replace_previous: bool
+) -> Result<(), String> {
+    state.update_storage(|storage| accept_contact_key(storage, &contact_id, &fingerprint, replace_previous))
+}
+
+#[tauri::command]
+pub fn reject_contact_key_cmd(
+    state: tauri::State<'_, KeyStore>,
+    contact_id: String,
+    fingerprint: String
+) -> Result<(), String> {
+    state.update_storage(|storage| reject_contact_key(storage, &contact_id, &fingerprint))
+}
+
+#[tauri::command]
+pub fn list_recipient_groups_cmd(state: tauri::State<'_, KeyStore>) -> Result<Vec<RecipientGroup>, String> {
+    state.with_storage(|storage| Ok(storage.groups.clone()))
+}
+
+#[tauri::command]
+pub fn create_recipient_group_cmd(
+    state: tauri::State<'_, KeyStore>,
+    name: String,
+    contact_ids: Vec<String>,
+    key_ids: Vec<String>
+) -> Result<RecipientGroup, String> {
+    let group = create_recipient_group(name, contact_ids, key_ids);
+    state.update_storage(|storage| {
+        upsert_recipient_group(storage, group.clone());
+        Ok(group)
+    })
+}
+
+#[tauri::command]
+pub fn save_recipient_group_cmd(state: tauri::State<'_, KeyStore>, group: RecipientGroup) -> Result<(), String> {
+    state.update_storage(|storage| {
+        upsert_recipient_group(storage, group);
+        Ok(())
+    })
+}
+
+#[tauri::command]
+pub fn delete_recipient_group_cmd(state: tauri::State<'_, KeyStore>, group_id: String) -> Result<(), String> {
+    state.update_storage(|storage| remove_recipient_group(storage, &group_id))
+}
+
+#[tauri::command]
+pub fn expand_recipient_groups_cmd(
+    state: tauri::State<'_, KeyStore>,
+    group_ids: Vec<String>
+) -> Result<Vec<String>, String> {
+    state.with_storage(|storage| expand_recipient_groups(storage, &group_ids))
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageLocation {
+    pub portable: bool,
+    pub config_dir: String,
+}
+
+#[tauri::command]
+pub fn get_storage_location_cmd() -> Result<StorageLocation, String> {
+    Ok(StorageLocation {
+        portable: is_portable_mode(),
+        config_dir: get_app_config_dir()?.to_string_lossy().to_string(),
+    })
+}
+
+#[tauri::command]
+pub fn list_profiles_cmd() -> Result<Vec<Profile>, String> {
+    Ok(load_profiles()?.profiles)
+}
+
+#[tauri::command]
+pub fn get_active_profile_cmd() -> Result<Profile, String> {
+    get_active_profile()
+}
+
+#[tauri::command]
+pub fn create_profile_cmd(name: String, passphrase_mode: Option<PassphraseMode>) -> Result<Profile, String> {
+    create_profile(&name, passphrase_mode.unwrap_or_default())
+}
+
+#[tauri::command]
+pub fn rename_profile_cmd(profile_id: String, name: String) -> Result<Profile, String> {
+    rename_profile(&profile_id, &name)
+}
+
+#[tauri::command]
+pub fn set_profile_passphrase_mode_cmd(
+    state: tauri::State<'_, KeyStore>,
+    profile_id: String,
+    passphrase_mode: PassphraseMode,
+    current_secret: String,
+    new_passphrase: Option<String>
+) -> Result<Profile, String> {
+    let (profile, passphrase) =
+        set_profile_passphrase_mode(&profile_id, passphrase_mode, &current_secret, new_passphrase.as_deref())?;
+
+    // The loaded store was re-encrypted, keep usage updates working
+    let path = get_profile_key_storage_path(&profile_id)?;
+    if state.loaded_storage(&path).is_some() {
+        let storage = load_key_storage(&passphrase, &path)?;
+        state.remember(&passphrase, &path, storage);
+    }
+
+    Ok(profile)
+}
+
+#[tauri::command]
+pub fn switch_profile_cmd(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, KeyStore>,
+    profile_id: String
+) -> Result<Profile, String> {
+    let profile = switch_profile(&profile_id)?;
+
+    // The frontend loads the key storage of the new profile
+    if let Ok(mut loaded) = state.loaded.lock() {
+        *loaded = None;
+    }
+    // Settings are per profile
+    if let Ok(settings) = load_settings() {
+        let _ = app.emit(SETTINGS_CHANGED_EVENT, settings);
+    }
+    Ok(profile)
+}
+
+#[tauri::command]
+pub fn delete_profile_cmd(profile_id: String) -> Result<(), String> {
+    delete_profile(&profile_id)
+}
+
+#[tauri::command]
+pub fn get_settings_cmd() -> Result<Settings, String> {
+    load_settings()
+}
+
+#[tauri::command]
+pub fn set_settings_cmd(app: tauri::AppHandle, settings: Settings) -> Result<Settings, String> {
+    let settings = save_settings(&settings)?;
+    let _ = app.emit(SETTINGS_CHANGED_EVENT, settings.clone());
+    Ok(settings)
+}
+
+#[tauri::command]
+pub fn reset_settings_cmd(app: tauri::AppHandle) -> Result<Settings, String> {
+    let settings = reset_settings()?;
+    let _ = app.emit(SETTINGS_CHANGED_EVENT, settings.clone());
+    Ok(settings)
+}
+
 #[tauri::command]
 pub fn get_user_home_directory() -> Result<String, String> {
     dirs::home_dir()
@@ -121,20 +856,48 @@ pub fn get_platform() -> String {
     std::env::consts::OS.to_string()
 }
 
+/// Files the app was asked to open that the frontend has not handled yet
+#[tauri::command]
+pub fn take_pending_open_files_cmd(state: tauri::State<'_, PendingOpenFiles>) -> Vec<OpenFilesRequest> {
+    state.take()
+}
+
 #[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct DirectoryItem {
     pub name: String,
     pub path: String,
     pub is_directory: bool,
     pub size: Option<u64>,
     pub modified: Option<String>,
+    pub kind: FileKind,
+    pub is_hidden: bool,
 }
 
+/// List a directory. Hidden entries are included unless `show_hidden` is
+/// false, and with `patterns` only files matching one of them are listed.
+/// Directories are always listed so they can be browsed.
 #[tauri::command]
-pub fn list_directory_contents(path: String) -> Result<Vec<DirectoryItem>, String> {
+pub async fn list_directory_contents(
+    path: String,
+    show_hidden: Option<bool>,
+    patterns: Option<Vec<String>>
+) -> Result<Vec<DirectoryItem>, String> {
+    let show_hidden = show_hidden.unwrap_or(true);
+    let patterns: Vec<String> = patterns
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|p| !p.trim().is_empty())
+        .collect();
+
+    // Reading metadata and file headers can be slow on network drives
+    run_blocking(move || read_directory_items(&path, show_hidden, &patterns)).await?
+}
+
+fn read_directory_items(path: &str, show_hidden: bool, patterns: &[String]) -> Result<Vec<DirectoryItem>, String> {
     use std::fs;
 
-    let entries = fs::read_dir(&path)
+    let entries = fs::read_dir(path)
         .map_err(|e| format!("Failed to read directory {}: {}", path, e))?;
 
     let mut items = Vec::new();
@@ -153,6 +916,14 @@ pub fn list_directory_contents(path: String) -> Result<Vec<DirectoryItem>, Strin
         let full_path = path_buf.to_string_lossy().to_string();
         let is_directory = metadata.is_dir();
 
+        let is_hidden = is_hidden(&name, &metadata);
+        if is_hidden && !show_hidden {
+            continue;
+        }
+        if !is_directory && !patterns.is_empty() && !patterns.iter().any(|p| matches_pattern(p, &name)) {
+            continue;
+        }
+
         let size = if is_directory {
             None
         } else {
@@ -169,12 +940,16 @@ pub fn list_directory_contents(path: String) -> Result<Vec<DirectoryItem>, Strin
                 datetime.format("%Y-%m-%d %H:%M").to_string()
             });
 
+        let kind = detect_file_kind(&path_buf);
+
         items.push(DirectoryItem {
             name,
             path: full_path,
             is_directory,
             size,
             modified,
+            kind,
+            is_hidden,
         });
     }
 
@@ -0,0 +1,595 @@
+use crate::key_storage::{current_timestamp, expired_recipient_warnings, key_fingerprint, KeyStorage};
+use crate::settings::Settings;
+use crate::verify::identities_for_recipients;
+use serde::{Deserialize, Serialize};
+
+/// Trust state of a contact key, tracked trust-on-first-use like SSH known_hosts
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum KeyTrust {
+    /// First key seen for the contact, or explicitly accepted
+    #[default]
+    Trusted,
+    /// Key appeared after the contact already had a trusted key; must be
+    /// accepted before files can be encrypted to it
+    Pending,
+    /// Key was superseded by a newer accepted key
+    Retired,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum KeyEvent {
+    Added,
+    Changed,
+    Accepted,
+    Rejected,
+    Removed,
+}
+
+/// A single entry in a contact's key history
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyHistoryEntry {
+    pub public_key: String,
+    pub fingerprint: String,
+    pub event: KeyEvent,
+    pub at: u64,
+}
+
+/// A public key belonging to a contact
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ContactKey {
+    pub public_key: String,
+    pub label: Option<String>,
+    pub added_at: u64,
+    #[serde(default)]
+    pub fingerprint: String,
+    #[serde(default)]
+    pub trust: KeyTrust,
+}
+
+impl ContactKey {
+    fn new(public_key: &str, label: Option<String>, trust: KeyTrust) -> Self {
+        let public_key = public_key.trim().to_string();
+        ContactKey {
+            fingerprint: key_fingerprint(&public_key),
+            public_key,
+            label,
+            added_at: current_timestamp(),
+            trust,
+        }
+    }
+
+    fn history(&self, event: KeyEvent) -> KeyHistoryEntry {
+        KeyHistoryEntry {
+            public_key: self.public_key.clone(),
+            fingerprint: self.fingerprint.clone(),
+            event,
+            at: current_timestamp(),
+        }
+    }
+}
+
+/// A person we encrypt files to, kept apart from our own identities
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Contact {
+    pub id: String,
+    pub name: String,
+    pub email: Option<String>,
+    #[serde(default)]
+    pub keys: Vec<ContactKey>,
+    pub notes: Option<String>,
+    pub created_at: u64,
+    #[serde(default)]
+    pub key_history: Vec<KeyHistoryEntry>,
+}
+
+impl Contact {
+    fn has_trusted_key(&self) -> bool {
+        self.keys.iter().any(|k| k.trust == KeyTrust::Trusted)
+    }
+
+    /// Fill in fingerprints missing from contacts stored before they were tracked
+    fn ensure_fingerprints(&mut self) {
+        for key in self.keys.iter_mut().filter(|k| k.fingerprint.is_empty()) {
+            key.fingerprint = key_fingerprint(&key.public_key);
+        }
+    }
+}
+
+/// Outcome of adding a key to an existing contact
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ContactKeyChange {
+    pub contact_id: String,
+    pub fingerprint: String,
+    pub trust: KeyTrust,
+    /// True when the contact already had a trusted key and the new one needs acceptance
+    pub changed: bool,
+    pub previous_fingerprints: Vec<String>,
+}
+
+/// A named set of recipients (e.g. "ops-team") that can be encrypted to at once
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RecipientGroup {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub contact_ids: Vec<String>, // every key of these contacts
+    #[serde(default)]
+    pub key_ids: Vec<String>, // individual stored keys, e.g. our own identities
+    pub created_at: u64,
+}
+
+/// Create a new Contact with current timestamp
+pub fn create_contact(name: String, email: Option<String>, public_keys: Vec<String>) -> Contact {
+    // Keys known when the contact is created are trusted on first use
+    let keys: Vec<ContactKey> = public_keys
+        .iter()
+        .map(|public_key| ContactKey::new(public_key, None, KeyTrust::Trusted))
+        .collect();
+    let key_history = keys.iter().map(|k| k.history(KeyEvent::Added)).collect();
+
+    Contact {
+        id: uuid::Uuid::new_v4().to_string(),
+        name,
+        email,
+        keys,
+        notes: None,
+        created_at: current_timestamp(),
+        key_history,
+    }
+}
+
+/// Create a new RecipientGroup with current timestamp
+pub fn create_recipient_group(
+    name: String,
+    contact_ids: Vec<String>,
+    key_ids: Vec<String>,
+) -> RecipientGroup {
+    RecipientGroup {
+        id: uuid::Uuid::new_v4().to_string(),
+        name,
+        contact_ids,
+        key_ids,
+        created_at: current_timestamp(),
+    }
+}
+
+/// Insert the contact, replacing any existing contact with the same id.
+/// Trust state and key history always come from the stored copy: keys that
+/// were not known before go through the same trust-on-first-use rules as
+/// `add_contact_key`, so a caller cannot mark a changed key as trusted.
+pub fn upsert_contact(storage: &mut KeyStorage, mut contact: Contact) {
+    contact.ensure_fingerprints();
+
+    let Some(existing) = storage.contacts.iter_mut().find(|c| c.id == contact.id) else {
+        for key in contact.keys.iter_mut() {
+            key.trust = KeyTrust::Trusted;
+        }
+        contact.key_history = contact.keys.iter().map(|k| k.history(KeyEvent::Added)).collect();
+        storage.contacts.push(contact);
+        return;
+    };
+
+    let had_trusted_key = existing.has_trusted_key();
+    let mut history = existing.key_history.clone();
+
+    for key in contact.keys.iter_mut() {
+        match existing.keys.iter().find(|k| k.public_key == key.public_key) {
+            Some(known) => {
+                key.trust = known.trust;
+                key.added_at = known.added_at;
+            }
+            None if had_trusted_key => {
+                key.trust = KeyTrust::Pending;
+                history.push(key.history(KeyEvent::Changed));
+            }
+            None => {
+                key.trust = KeyTrust::Trusted;
+                history.push(key.history(KeyEvent::Added));
+            }
+        }
+    }
+
+    for removed in existing
+        .keys
+        .iter()
+        .filter(|k| !contact.keys.iter().any(|c| c.public_key == k.public_key))
+    {
+        history.push(removed.history(KeyEvent::Removed));
+    }
+
+    contact.key_history = history;
+    *existing = contact;
+}
+
+fn find_contact_mut<'a>(storage: &'a mut KeyStorage, contact_id: &str) -> Result<&'a mut Contact, String> {
+    storage
+        .contacts
+        .iter_mut()
+        .find(|c| c.id == contact_id)
+        .ok_or(format!("Contact not found: {}", contact_id))
+}
+
+/// Add a key to an existing contact. The first key of a contact is trusted
+/// on first use; any later, different key is recorded as a change and stays
+/// pending until accepted.
+pub fn add_contact_key(
+    storage: &mut KeyStorage,
+    contact_id: &str,
+    public_key: &str,
+    label: Option<String>,
+) -> Result<ContactKeyChange, String> {
+    let contact = find_contact_mut(storage, contact_id)?;
+    contact.ensure_fingerprints();
+
+    if let Some(known) = contact.keys.iter().find(|k| k.public_key == public_key.trim()) {
+        return Ok(ContactKeyChange {
+            contact_id: contact.id.clone(),
+            fingerprint: known.fingerprint.clone(),
+            trust: known.trust,
+            changed: false,
+            previous_fingerprints: Vec::new(),
+        });
+    }
+
+    let changed = contact.has_trusted_key();
+    let trust = if changed { KeyTrust::Pending } else { KeyTrust::Trusted };
+    let key = ContactKey::new(public_key, label, trust);
+
+    contact.key_history.push(key.history(if changed { KeyEvent::Changed } else { KeyEvent::Added }));
+    let change = ContactKeyChange {
+        contact_id: contact.id.clone(),
+        fingerprint: key.fingerprint.clone(),
+        trust,
+        changed,
+        previous_fingerprints: contact
+            .keys
+            .iter()
+            .filter(|k| k.trust == KeyTrust::Trusted)
+            .map(|k| k.fingerprint.clone())
+            .collect(),
+    };
+    contact.keys.push(key);
+
+    Ok(change)
+}
+
+/// Accept a pending contact key. With `replace_previous` the keys trusted
+/// so far are retired, as when a colleague rotated their key.
+pub fn accept_contact_key(
+    storage: &mut KeyStorage,
+    contact_id: &str,
+    fingerprint: &str,
+    replace_previous: bool,
+) -> Result<(), String> {
+    let contact = find_contact_mut(storage, contact_id)?;
+    contact.ensure_fingerprints();
+
+    let index = contact
+        .keys
+        .iter()
+        .position(|k| k.fingerprint == fingerprint)
+        .ok_or(format!("Contact \"{}\" has no key {}", contact.name, fingerprint))?;
+
+    if replace_previous {
+        for (i, key) in contact.keys.iter_mut().enumerate() {
+            if i != index && key.trust == KeyTrust::Trusted {
+                key.trust = KeyTrust::Retired;
+            }
+        }
+    }
+
+    let key = &mut contact.keys[index];
+    key.trust = KeyTrust::Trusted;
+    let entry = key.history(KeyEvent::Accepted);
+    contact.key_history.push(entry);
+
+    Ok(())
+}
+
+/// Reject a pending contact key, removing it from the contact
+pub fn reject_contact_key(storage: &mut KeyStorage, contact_id: &str, fingerprint: &str) -> Result<(), String> {
+    let contact = find_contact_mut(storage, contact_id)?;
+    contact.ensure_fingerprints();
+
+    let index = contact
+        .keys
+        .iter()
+        .position(|k| k.fingerprint == fingerprint && k.trust == KeyTrust::Pending)
+        .ok_or(format!("Contact \"{}\" has no pending key {}", contact.name, fingerprint))?;
+
+    let key = contact.keys.remove(index);
+    contact.key_history.push(key.history(KeyEvent::Rejected));
+
+    Ok(())
+}
+
+/// Refuse recipients that are pending contact keys, i.e. changed keys that
+/// have not been explicitly accepted yet
+pub fn check_recipients_trusted(storage: &KeyStorage, recipients: &[String]) -> Result<(), String> {
+    for contact in &storage.contacts {
+        for key in contact.keys.iter().filter(|k| k.trust == KeyTrust::Pending) {
+            if recipients.iter().any(|r| r.trim() == key.public_key) {
+                return Err(format!(
+                    "The key of contact \"{}\" changed (new fingerprint {}). Accept the new key before encrypting to it.",
+                    contact.name,
+                    key_fingerprint(&key.public_key)
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Warn about recipients that are retired contact keys, i.e. keys replaced
+/// by a newer accepted key of the same contact
+pub fn retired_recipient_warnings(storage: &KeyStorage, recipients: &[String]) -> Vec<String> {
+    storage
+        .contacts
+        .iter()
+        .filter(|contact| {
+            contact
+                .keys
+                .iter()
+                .any(|k| k.trust == KeyTrust::Retired && recipients.iter().any(|r| r.trim() == k.public_key))
+        })
+        .map(|contact| format!("A recipient is an old key of contact \"{}\" that was replaced by a newer key", contact.name))
+        .collect()
+}
+
+/// Expand recipient groups, add the self recipients and check the final
+/// recipients against the key store: pending contact keys are refused, and
+/// expired keys, retired contact keys and the lack of an own key are warned
+/// about. Without a key store only the self recipients are added.
+pub fn prepare_recipients(
+    storage: Option<&KeyStorage>,
+    settings: &Settings,
+    recipients: &mut Vec<String>,
+    group_ids: &[String],
+) -> Result<Vec<String>, String> {
+    if !group_ids.is_empty() {
+        let storage = storage.ok_or("Key storage is not loaded")?;
+        for public_key in expand_recipient_groups(storage, group_ids)? {
+            if !recipients.iter().any(|r| r.trim() == public_key) {
+                recipients.push(public_key);
+            }
+        }
+    }
+
+    if recipients.is_empty() {
+        return Err("No recipients specified".to_string());
+    }
+    settings.append_self_recipients(recipients);
+
+    let Some(storage) = storage else {
+        return Ok(Vec::new());
+    };
+    check_recipients_trusted(storage, recipients)?;
+
+    let mut warnings = expired_recipient_warnings(&storage.keys, recipients);
+    warnings.extend(retired_recipient_warnings(storage, recipients));
+    if identities_for_recipients(&storage.keys, recipients).is_empty() {
+        warnings.push(
+            "None of the recipients is one of your own keys, so you will not be able to decrypt this file".to_string(),
+        );
+    }
+    Ok(warnings)
+}
+
+/// Find a contact by id or exact name
+pub fn find_contact<'a>(storage: &'a KeyStorage, query: &str) -> Option<&'a Contact> {
+    let query = query.trim();
+    storage.contacts.iter().find(|c| c.id == query || c.name == query)
+}
+
+/// Find a recipient group by id or exact name
+pub fn find_recipient_group<'a>(storage: &'a KeyStorage, query: &str) -> Option<&'a RecipientGroup> {
+    let query = query.trim();
+    storage.groups.iter().find(|g| g.id == query || g.name == query)
+}
+
+/// Remove a contact and any group membership referring to it
+pub fn remove_contact(storage: &mut KeyStorage, contact_id: &str) -> Result<(), String> {
+    let before = storage.contacts.len();
+    storage.contacts.retain(|c| c.id != contact_id);
+    if storage.contacts.len() == before {
+        return Err(format!("Contact not found: {}", contact_id));
+    }
+
+    for group in storage.groups.iter_mut() {
+        group.contact_ids.retain(|id| id != contact_id);
+    }
+
+    Ok(())
+}
+
+/// Insert the group, replacing any existing group with the same id
+pub fn upsert_recipient_group(storage: &mut KeyStorage, group: RecipientGroup) {
+    match storage.groups.iter_mut().find(|g| g.id == group.id) {
+        Some(existing) => *existing = group,
+        None => storage.groups.push(group),
+    }
+}
+
+/// Remove a recipient group
+pub fn remove_recipient_group(storage: &mut KeyStorage, group_id: &str) -> Result<(), String> {
+    let before = storage.groups.len();
+    storage.groups.retain(|g| g.id != group_id);
+    if storage.groups.len() == before {
+        return Err(format!("Recipient group not found: {}", group_id));
+    }
+    Ok(())
+}
+
+/// Expand recipient groups into the public keys of their members, without duplicates
+pub fn expand_recipient_groups(
+    storage: &KeyStorage,
+    group_ids: &[String],
+) -> Result<Vec<String>, String> {
+    let mut recipients: Vec<String> = Vec::new();
+    let mut push = |public_key: &str| {
+        if !recipients.iter().any(|r| r == public_key) {
+            recipients.push(public_key.to_string());
+        }
+    };
+
+    for group_id in group_ids {
+        let group = storage
+            .groups
+            .iter()
+            .find(|g| &g.id == group_id)
+            .ok_or(format!("Recipient group not found: {}", group_id))?;
+
+        for contact_id in &group.contact_ids {
+            let contact = storage
+                .contacts
+                .iter()
+                .find(|c| &c.id == contact_id)
+                .ok_or(format!(
+                    "Group \"{}\" refers to unknown contact {}",
+                    group.name, contact_id
+                ))?;
+            contact
+                .keys
+                .iter()
+                .filter(|k| k.trust != KeyTrust::Retired)
+                .for_each(|k| push(&k.public_key));
+        }
+
+        for key_id in &group.key_ids {
+            let key = storage
+                .keys
+                .iter()
+                .find(|k| &k.id == key_id)
+                .ok_or(format!(
+                    "Group \"{}\" refers to unknown key {}",
+                    group.name, key_id
+                ))?;
+            push(&key.public_key);
+        }
+    }
+
+    Ok(recipients)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OLD_KEY: &str = "age1oldkeyoldkeyoldkey";
+    const NEW_KEY: &str = "age1newkeynewkeynewkey";
+
+    fn storage_with_contact() -> (KeyStorage, String) {
+        let mut storage = KeyStorage::default();
+        let contact = create_contact("Alice".to_string(), None, vec![OLD_KEY.to_string()]);
+        let id = contact.id.clone();
+        upsert_contact(&mut storage, contact);
+        (storage, id)
+    }
+
+    fn trust_of(storage: &KeyStorage, public_key: &str) -> KeyTrust {
+        storage.contacts[0].keys.iter().find(|k| k.public_key == public_key).unwrap().trust
+    }
+
+    #[test]
+    fn first_key_is_trusted() {
+        let mut storage = KeyStorage::default();
+        let contact = create_contact("Bob".to_string(), None, Vec::new());
+        let id = contact.id.clone();
+        upsert_contact(&mut storage, contact);
+
+        let change = add_contact_key(&mut storage, &id, OLD_KEY, None).unwrap();
+        assert_eq!(change.trust, KeyTrust::Trusted);
+        assert!(!change.changed);
+        assert!(check_recipients_trusted(&storage, &[OLD_KEY.to_string()]).is_ok());
+    }
+
+    #[test]
+    fn changed_key_is_pending_and_refused() {
+        let (mut storage, id) = storage_with_contact();
+
+        let change = add_contact_key(&mut storage, &id, NEW_KEY, None).unwrap();
+        assert_eq!(change.trust, KeyTrust::Pending);
+        assert!(change.changed);
+        assert_eq!(change.previous_fingerprints, vec![key_fingerprint(OLD_KEY)]);
+
+        assert!(check_recipients_trusted(&storage, &[NEW_KEY.to_string()]).is_err());
+        assert!(check_recipients_trusted(&storage, &[OLD_KEY.to_string()]).is_ok());
+    }
+
+    #[test]
+    fn accepting_with_replace_previous_retires_old_key() {
+        let (mut storage, id) = storage_with_contact();
+        add_contact_key(&mut storage, &id, NEW_KEY, None).unwrap();
+
+        accept_contact_key(&mut storage, &id, &key_fingerprint(NEW_KEY), true).unwrap();
+        assert_eq!(trust_of(&storage, NEW_KEY), KeyTrust::Trusted);
+        assert_eq!(trust_of(&storage, OLD_KEY), KeyTrust::Retired);
+        assert!(check_recipients_trusted(&storage, &[NEW_KEY.to_string()]).is_ok());
+        assert_eq!(retired_recipient_warnings(&storage, &[OLD_KEY.to_string()]).len(), 1);
+    }
+
+    #[test]
+    fn accepting_without_replace_previous_keeps_old_key() {
+        let (mut storage, id) = storage_with_contact();
+        add_contact_key(&mut storage, &id, NEW_KEY, None).unwrap();
+
+        accept_contact_key(&mut storage, &id, &key_fingerprint(NEW_KEY), false).unwrap();
+        assert_eq!(trust_of(&storage, NEW_KEY), KeyTrust::Trusted);
+        assert_eq!(trust_of(&storage, OLD_KEY), KeyTrust::Trusted);
+    }
+
+    #[test]
+    fn rejecting_removes_pending_key() {
+        let (mut storage, id) = storage_with_contact();
+        add_contact_key(&mut storage, &id, NEW_KEY, None).unwrap();
+
+        reject_contact_key(&mut storage, &id, &key_fingerprint(NEW_KEY)).unwrap();
+        assert_eq!(storage.contacts[0].keys.len(), 1);
+        assert!(reject_contact_key(&mut storage, &id, &key_fingerprint(OLD_KEY)).is_err());
+    }
+
+    #[test]
+    fn upsert_cannot_bypass_pending() {
+        let (mut storage, id) = storage_with_contact();
+
+        // A caller adds a new key and claims it is trusted
+        let mut edited = storage.contacts[0].clone();
+        let mut forged = edited.keys[0].clone();
+        forged.public_key = NEW_KEY.to_string();
+        forged.fingerprint = key_fingerprint(NEW_KEY);
+        forged.trust = KeyTrust::Trusted;
+        edited.keys.push(forged);
+        edited.key_history.clear();
+        upsert_contact(&mut storage, edited);
+
+        assert_eq!(trust_of(&storage, NEW_KEY), KeyTrust::Pending);
+        assert!(check_recipients_trusted(&storage, &[NEW_KEY.to_string()]).is_err());
+        assert!(storage.contacts[0]
+            .key_history
+            .iter()
+            .any(|e| e.public_key == NEW_KEY && e.event == KeyEvent::Changed));
+
+        // Nor can it promote the stored pending key
+        let mut edited = storage.contacts[0].clone();
+        edited.keys.iter_mut().for_each(|k| k.trust = KeyTrust::Trusted);
+        upsert_contact(&mut storage, edited);
+        assert_eq!(trust_of(&storage, NEW_KEY), KeyTrust::Pending);
+        assert_eq!(storage.contacts[0].id, id);
+    }
+
+    #[test]
+    fn new_contact_keys_are_trusted_on_first_use() {
+        let mut storage = KeyStorage::default();
+        let mut contact = create_contact("Carol".to_string(), None, vec![OLD_KEY.to_string()]);
+        contact.keys[0].trust = KeyTrust::Pending;
+        upsert_contact(&mut storage, contact);
+        assert_eq!(trust_of(&storage, OLD_KEY), KeyTrust::Trusted);
+    }
+}
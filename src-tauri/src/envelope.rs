@@ -0,0 +1,376 @@
+use crate::age::get_age_exe_path;
+use crate::fs_util::{commit_temp_file, temp_path_for};
+use crate::verify::{hash_file, IdentityFiles};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, UNIX_EPOCH};
+
+/// First line of an envelope payload. It is followed by one line of JSON
+/// metadata and then the file contents.
+const ENVELOPE_MAGIC: &str = "tauriage-envelope/v1";
+
+/// Longest metadata line accepted when opening an envelope
+const MAX_METADATA_LEN: u64 = 64 * 1024;
+
+/// Name used when an envelope carries no usable file name
+const FALLBACK_NAME: &str = "decrypted";
+
+/// Original file details stored inside the encrypted payload
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct EnvelopeMetadata {
+    pub name: String,
+    pub size: u64,
+    /// Modification time in milliseconds since the Unix epoch
+    pub modified: Option<u64>,
+    /// Unix permission bits
+    pub mode: Option<u32>,
+    /// SHA-256 of the contents, as "SHA256:<base64>"
+    pub sha256: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct EnvelopeDecryptionResult {
+    pub success: bool,
+    pub input_file: String,
+    pub output_file: String,
+    pub metadata: EnvelopeMetadata,
+    /// Metadata that could not be restored
+    #[serde(default)]
+    pub warnings: Vec<String>,
+}
+
+fn format_digest(digest: [u8; 32]) -> String {
+    use base64::{engine::general_purpose::STANDARD_NO_PAD, Engine as _};
+    format!("SHA256:{}", STANDARD_NO_PAD.encode(digest))
+}
+
+/// Reader that hashes and counts everything read through it
+struct HashingReader<R> {
+    inner: R,
+    hasher: Sha256,
+    len: u64,
+}
+
+impl<R: Read> HashingReader<R> {
+    fn new(inner: R) -> Self {
+        HashingReader {
+            inner,
+            hasher: Sha256::new(),
+            len: 0,
+        }
+    }
+
+    /// Whether the data read so far matches the size and hash in the metadata
+    fn matches(self, metadata: &EnvelopeMetadata) -> bool {
+        self.len == metadata.size && format_digest(self.hasher.finalize().into()) == metadata.sha256
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.update(&buf[..read]);
+        self.len += read as u64;
+        Ok(read)
+    }
+}
+
+fn read_metadata(path: &Path) -> Result<EnvelopeMetadata, String> {
+    let file_meta = fs::metadata(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    if !file_meta.is_file() {
+        return Err(format!("{} is not a regular file", path.display()));
+    }
+
+    let modified = file_meta
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|since| since.as_millis() as u64);
+    #[cfg(unix)]
+    let mode = {
+        use std::os::unix::fs::PermissionsExt;
+        Some(file_meta.permissions().mode() & 0o7777)
+    };
+    #[cfg(not(unix))]
+    let mode = None;
+
+    Ok(EnvelopeMetadata {
+        name: path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        size: file_meta.len(),
+        modified,
+        mode,
+        sha256: format_digest(hash_file(path)?),
+    })
+}
+
+/// Random outer file name that says nothing about the contents
+pub fn random_envelope_name(armor: bool) -> String {
+    let stem = uuid::Uuid::new_v4().simple().to_string();
+    crate::output_paths::default_output_name(&stem, crate::output_paths::FileOperation::Encrypt, armor)
+}
+
+/// The stored name reduced to a plain file name, so that a crafted envelope
+/// cannot write outside the chosen directory
+pub fn restored_file_name(metadata: &EnvelopeMetadata) -> String {
+    let name = metadata.name.trim();
+    let is_plain = !name.is_empty()
+        && name != "."
+        && name != ".."
+        && !name.contains(['/', '\\', '\0'])
+        && Path::new(name).file_name().is_some_and(|n| n == name);
+    if is_plain {
+        name.to_string()
+    } else {
+        FALLBACK_NAME.to_string()
+    }
+}
+
+/// Wait for age and turn a failed run into an error with its message
+fn wait_for_age(child: Child, action: &str) -> Result<(), String> {
+    let output = child
+        .wait_with_output()
+        .map_err(|e| format!("Failed to execute age {}: {}", action, e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "age {} failed: {}",
+            action,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+fn write_payload(input: &Path, metadata: &EnvelopeMetadata, mut writer: impl Write) -> Result<bool, String> {
+    let json = serde_json::to_string(metadata).map_err(|e| format!("Failed to serialize metadata: {}", e))?;
+    writeln!(writer, "{}\n{}", ENVELOPE_MAGIC, json).map_err(|e| format!("Failed to write envelope: {}", e))?;
+
+    let file = File::open(input).map_err(|e| format!("Failed to read {}: {}", input.display(), e))?;
+    let mut reader = HashingReader::new(file);
+    io::copy(&mut reader, &mut writer).map_err(|e| format!("Failed to write envelope: {}", e))?;
+    Ok(reader.matches(metadata))
+}
+
+/// Encrypt a file together with its name and metadata. The output is a
+/// regular age file whose plaintext is the envelope.
+pub fn encrypt_envelope(
+    input: &Path,
+    output: &Path,
+    recipients: &[String],
+    armor: bool,
+) -> Result<EnvelopeMetadata, String> {
+    let metadata = read_metadata(input)?;
+    let temp_output = temp_path_for(output);
+
+    let mut cmd = Command::new(get_age_exe_path("age")?);
+    if armor {
+        cmd.arg("--armor");
+    }
+    for recipient in recipients {
+        cmd.arg("-r").arg(recipient);
+    }
+    cmd.arg("-o")
+        .arg(&temp_output)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped());
+
+    let mut child = cmd.spawn().map_err(|e| format!("Failed to execute age encrypt: {}", e))?;
+    let stdin = child.stdin.take().ok_or("Failed to write to age")?;
+    // stdin is closed when write_payload returns, so age sees the end of the data
+    let written = write_payload(input, &metadata, stdin);
+
+    let result = wait_for_age(child, "encrypt").and_then(|_| {
+        if !written? {
+            return Err(format!("{} changed while it was being encrypted", input.display()));
+        }
+        commit_temp_file(&temp_output, output).map_err(|e| format!("Failed to write {}: {}", output.display(), e))
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_output);
+    }
+    result.map(|_| metadata)
+}
+
+fn read_line_limited(reader: &mut impl BufRead) -> Result<String, String> {
+    let mut line = Vec::new();
+    reader
+        .take(MAX_METADATA_LEN)
+        .read_until(b'\n', &mut line)
+        .map_err(|e| format!("Failed to read decrypted data: {}", e))?;
+    if line.pop() != Some(b'\n') {
+        return Err("The file is not a TauriAge envelope".to_string());
+    }
+    String::from_utf8(line).map_err(|_| "The file is not a TauriAge envelope".to_string())
+}
+
+fn read_envelope_header(reader: &mut impl BufRead) -> Result<EnvelopeMetadata, String> {
+    if read_line_limited(reader)? != ENVELOPE_MAGIC {
+        return Err("The file is not a TauriAge envelope".to_string());
+    }
+    serde_json::from_str(&read_line_limited(reader)?).map_err(|e| format!("Invalid envelope metadata: {}", e))
+}
+
+/// Apply the stored modification time and permissions to a restored file
+fn restore_metadata(path: &Path, metadata: &EnvelopeMetadata) -> Vec<String> {
+    let mut warnings = Vec::new();
+
+    if let Some(modified) = metadata.modified {
+        let time = UNIX_EPOCH + Duration::from_millis(modified);
+        if let Err(e) = File::options().write(true).open(path).and_then(|f| f.set_modified(time)) {
+            warnings.push(format!("Failed to restore the modification time: {}", e));
+        }
+    }
+
+    #[cfg(unix)]
+    if let Some(mode) = metadata.mode {
+        use std::os::unix::fs::PermissionsExt;
+        if let Err(e) = fs::set_permissions(path, fs::Permissions::from_mode(mode & 0o777)) {
+            warnings.push(format!("Failed to restore the permissions: {}", e));
+        }
+    }
+
+    warnings
+}
+
+/// Decrypt an envelope. `choose_output` picks the output path once the stored
+/// metadata is known. The contents are checked against the stored size and
+/// hash before the file is moved into place. Returns the output path, the
+/// metadata and warnings about metadata that could not be restored.
+pub fn decrypt_envelope<F>(
+    input: &Path,
+    identity: &str,
+    choose_output: F,
+) -> Result<(PathBuf, EnvelopeMetadata, Vec<String>), String>
+where
+    F: FnOnce(&EnvelopeMetadata) -> Result<PathBuf, String>,
+{
+    let identity_files = IdentityFiles::create([identity])?;
+    let mut cmd = Command::new(get_age_exe_path("age")?);
+    cmd.arg("-d");
+    identity_files.add_args(&mut cmd);
+    cmd.arg(input)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    let mut child = cmd.spawn().map_err(|e| format!("Failed to execute age decrypt: {}", e))?;
+    let mut reader = BufReader::new(child.stdout.take().ok_or("Failed to read age output")?);
+
+    let prepared = read_envelope_header(&mut reader).and_then(|metadata| {
+        let output = choose_output(&metadata)?;
+        Ok((metadata, output))
+    });
+    let (metadata, output) = match prepared {
+        Ok(prepared) => prepared,
+        Err(e) => {
+            let _ = child.kill();
+            let stderr = child
+                .wait_with_output()
+                .map(|o| String::from_utf8_lossy(&o.stderr).trim().to_string())
+                .unwrap_or_default();
+            // A failed decryption explains more than a missing envelope header
+            return Err(if stderr.is_empty() { e } else { format!("age decrypt failed: {}", stderr) });
+        }
+    };
+
+    let temp_output = temp_path_for(&output);
+    let mut contents = HashingReader::new(reader);
+    let copied = File::create(&temp_output)
+        .and_then(|mut file| io::copy(&mut contents, &mut file))
+        .map_err(|e| format!("Failed to write {}: {}", output.display(), e));
+    if let Err(e) = copied {
+        // age may be blocked writing to the pipe, so stop it before waiting
+        drop(contents);
+        let _ = child.kill();
+        let _ = child.wait();
+        let _ = fs::remove_file(&temp_output);
+        return Err(e);
+    }
+
+    let result = wait_for_age(child, "decrypt").and_then(|_| {
+        if !contents.matches(&metadata) {
+            return Err("The decrypted contents do not match the size and hash stored in the envelope".to_string());
+        }
+        commit_temp_file(&temp_output, &output).map_err(|e| format!("Failed to write {}: {}", output.display(), e))
+    });
+    if let Err(e) = result {
+        let _ = fs::remove_file(&temp_output);
+        return Err(e);
+    }
+
+    let warnings = restore_metadata(&output, &metadata);
+    Ok((output, metadata, warnings))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn metadata_named(name: &str) -> EnvelopeMetadata {
+        EnvelopeMetadata {
+            name: name.to_string(),
+            size: 0,
+            modified: None,
+            mode: None,
+            sha256: String::new(),
+        }
+    }
+
+    #[test]
+    fn restored_file_name_keeps_plain_names() {
+        assert_eq!(restored_file_name(&metadata_named("report.pdf")), "report.pdf");
+        assert_eq!(restored_file_name(&metadata_named(" .hidden ")), ".hidden");
+    }
+
+    #[test]
+    fn restored_file_name_rejects_paths() {
+        for name in ["../x", "a/b", "a\\b", "/etc/passwd", "..", ".", "", "  ", "a\0b"] {
+            assert_eq!(restored_file_name(&metadata_named(name)), FALLBACK_NAME, "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn read_envelope_header_parses_metadata() {
+        let metadata = metadata_named("report.pdf");
+        let data = format!("{}\n{}\ncontents", ENVELOPE_MAGIC, serde_json::to_string(&metadata).unwrap());
+        let mut reader = Cursor::new(data.into_bytes());
+
+        assert_eq!(read_envelope_header(&mut reader).unwrap(), metadata);
+        let mut rest = String::new();
+        reader.read_to_string(&mut rest).unwrap();
+        assert_eq!(rest, "contents");
+    }
+
+    #[test]
+    fn read_envelope_header_rejects_other_data() {
+        let mut wrong_magic = Cursor::new(b"tauriage-envelope/v2\n{}\n".to_vec());
+        assert!(read_envelope_header(&mut wrong_magic).is_err());
+
+        let mut no_newline = Cursor::new(ENVELOPE_MAGIC.as_bytes().to_vec());
+        assert!(read_envelope_header(&mut no_newline).is_err());
+
+        let mut bad_json = Cursor::new(format!("{}\nnot json\n", ENVELOPE_MAGIC).into_bytes());
+        assert!(read_envelope_header(&mut bad_json).is_err());
+    }
+
+    #[test]
+    fn read_envelope_header_limits_metadata_length() {
+        let long_name = "a".repeat(MAX_METADATA_LEN as usize);
+        let data = format!(
+            "{}\n{}\n",
+            ENVELOPE_MAGIC,
+            serde_json::to_string(&metadata_named(&long_name)).unwrap()
+        );
+        assert!(read_envelope_header(&mut Cursor::new(data.into_bytes())).is_err());
+    }
+}
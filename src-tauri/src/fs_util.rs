@@ -0,0 +1,79 @@
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Hidden temporary path in the same directory as `path`, so that renaming it
+/// over `path` never crosses a filesystem boundary
+pub fn temp_path_for(path: &Path) -> PathBuf {
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let temp_name = format!(".{}.{}.tmp", file_name, uuid::Uuid::new_v4().simple());
+
+    match path.parent() {
+        Some(parent) => parent.join(temp_name),
+        None => PathBuf::from(temp_name),
+    }
+}
+
+/// Flush directory metadata so that a completed rename survives a crash.
+/// Directories cannot be opened for syncing on Windows, where this is a no-op.
+pub fn sync_parent_dir(path: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        File::open(parent)?.sync_all()?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+
+    Ok(())
+}
+
+/// Write `data` to `path` atomically: the data goes to a temporary file in the
+/// same directory, is fsynced, and is then renamed over the destination.
+/// A crash or a full disk leaves either the old or the new file, never a mix.
+pub fn write_atomic(path: &Path, data: &[u8]) -> io::Result<()> {
+    let temp_path = temp_path_for(path);
+
+    let result = (|| {
+        let mut file = File::create(&temp_path)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+        drop(file);
+        fs::rename(&temp_path, path)?;
+        sync_parent_dir(path)
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+
+    result
+}
+
+/// Move a completely written temporary file over `path`, fsyncing its data
+/// before the rename and the directory entry after it
+pub fn commit_temp_file(temp_path: &Path, path: &Path) -> io::Result<()> {
+    // Windows only flushes handles opened for writing
+    fs::OpenOptions::new().write(true).open(temp_path)?.sync_all()?;
+    fs::rename(temp_path, path)?;
+    sync_parent_dir(path)
+}
+
+/// Create a new file readable only by the current user, for secrets handed
+/// to the age binary
+pub fn write_private_file(path: &Path, data: &[u8]) -> io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)?.write_all(data)
+}
@@ -0,0 +1,319 @@
+use crate::key_storage::StoredKey;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// How an incoming key relates to the keys already in the store
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ImportStatus {
+    /// No stored key has this public key
+    New,
+    /// A stored key has the same public key and nothing to add
+    Identical,
+    /// A stored key has the same public key but differs in some way
+    Conflict,
+}
+
+/// A difference between an incoming key and the stored key with the same public key
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", tag = "kind")]
+pub enum ImportConflict {
+    NameDiffers { existing: String, incoming: String },
+    CommentDiffers,
+    TagsDiffer,
+    /// The stored key is public-only and the import carries its private key
+    PrivateKeyAvailable,
+    /// Both have a private key and they differ, e.g. an SSH key re-encrypted with a new passphrase
+    PrivateKeyDiffers,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportPlanEntry {
+    pub incoming: StoredKey,
+    pub status: ImportStatus,
+    pub existing_id: Option<String>,
+    pub conflicts: Vec<ImportConflict>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportPlan {
+    pub entries: Vec<ImportPlanEntry>,
+    pub new_count: usize,
+    pub identical_count: usize,
+    pub conflict_count: usize,
+}
+
+/// What to do with conflicting entries
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum ImportResolution {
+    /// Leave conflicting stored keys untouched
+    SkipConflicts,
+    /// Keep stored names and values, only fill in what the stored key lacks
+    /// (private key, comment, tags)
+    #[default]
+    MergeMissing,
+    /// Take name, comment, tags and private key from the imported key
+    PreferIncoming,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportResult {
+    pub keys: Vec<StoredKey>,
+    pub added: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+    pub skipped: usize,
+}
+
+fn same_public_key(a: &StoredKey, b: &StoredKey) -> bool {
+    a.public_key.trim() == b.public_key.trim()
+}
+
+fn find_conflicts(existing: &StoredKey, incoming: &StoredKey) -> Vec<ImportConflict> {
+    let mut conflicts = Vec::new();
+
+    if existing.name != incoming.name {
+        conflicts.push(ImportConflict::NameDiffers {
+            existing: existing.name.clone(),
+            incoming: incoming.name.clone(),
+        });
+    }
+    if incoming.comment.is_some() && existing.comment != incoming.comment {
+        conflicts.push(ImportConflict::CommentDiffers);
+    }
+    if !incoming.tags.is_empty() && existing.tags != incoming.tags {
+        conflicts.push(ImportConflict::TagsDiffer);
+    }
+    match (&existing.private_key, &incoming.private_key) {
+        (None, Some(_)) => conflicts.push(ImportConflict::PrivateKeyAvailable),
+        (Some(a), Some(b)) if a.trim() != b.trim() => {
+            conflicts.push(ImportConflict::PrivateKeyDiffers)
+        }
+        _ => {}
+    }
+
+    conflicts
+}
+
+/// Match incoming keys against stored keys by public key and classify them.
+/// Keys repeated within the import are only planned once.
+pub fn plan_key_import(existing: &[StoredKey], incoming: &[StoredKey]) -> ImportPlan {
+    let mut entries: Vec<ImportPlanEntry> = Vec::new();
+
+    for key in incoming {
+        if entries.iter().any(|e| same_public_key(&e.incoming, key)) {
+            continue;
+        }
+
+        let entry = match existing.iter().find(|e| same_public_key(e, key)) {
+            None => ImportPlanEntry {
+                incoming: key.clone(),
+                status: ImportStatus::New,
+                existing_id: None,
+                conflicts: Vec::new(),
+            },
+            Some(stored) => {
+                let conflicts = find_conflicts(stored, key);
+                ImportPlanEntry {
+                    incoming: key.clone(),
+                    status: if conflicts.is_empty() {
+                        ImportStatus::Identical
+                    } else {
+                        ImportStatus::Conflict
+                    },
+                    existing_id: Some(stored.id.clone()),
+                    conflicts,
+                }
+            }
+        };
+        entries.push(entry);
+    }
+
+    let count = |status| entries.iter().filter(|e| e.status == status).count();
+    ImportPlan {
+        new_count: count(ImportStatus::New),
+        identical_count: count(ImportStatus::Identical),
+        conflict_count: count(ImportStatus::Conflict),
+        entries,
+    }
+}
+
+/// Resolve one conflict. Ids, creation dates and usage metadata of the stored
+/// key are always kept.
+fn resolve(stored: &mut StoredKey, incoming: &StoredKey, resolution: ImportResolution) {
+    match resolution {
+        ImportResolution::SkipConflicts => {}
+        ImportResolution::MergeMissing => {
+            if stored.private_key.is_none() {
+                stored.private_key = incoming.private_key.clone();
+            }
+            if stored.comment.is_none() {
+                stored.comment = incoming.comment.clone();
+            }
+            for tag in &incoming.tags {
+                if !stored.tags.contains(tag) {
+                    stored.tags.push(tag.clone());
+                }
+            }
+        }
+        ImportResolution::PreferIncoming => {
+            stored.name = incoming.name.clone();
+            if incoming.private_key.is_some() {
+                stored.private_key = incoming.private_key.clone();
+            }
+            if incoming.comment.is_some() {
+                stored.comment = incoming.comment.clone();
+            }
+            if !incoming.tags.is_empty() {
+                stored.tags = incoming.tags.clone();
+            }
+            if incoming.expires_at.is_some() {
+                stored.expires_at = incoming.expires_at;
+            }
+        }
+    }
+}
+
+/// Apply an import plan to the stored keys. `overrides` maps incoming public
+/// keys to a resolution that replaces the default for that entry.
+pub fn apply_key_import(
+    existing: &[StoredKey],
+    incoming: &[StoredKey],
+    resolution: ImportResolution,
+    overrides: &HashMap<String, ImportResolution>,
+) -> ImportResult {
+    let plan = plan_key_import(existing, incoming);
+    let mut keys = existing.to_vec();
+    let (mut added, mut updated, mut unchanged, mut skipped) = (0, 0, 0, 0);
+
+    for entry in plan.entries {
+        match entry.status {
+            ImportStatus::New => {
+                keys.push(entry.incoming);
+                added += 1;
+            }
+            ImportStatus::Identical => unchanged += 1,
+            ImportStatus::Conflict => {
+                let resolution = overrides
+                    .get(entry.incoming.public_key.trim())
+                    .copied()
+                    .unwrap_or(resolution);
+                let Some(stored) = keys
+                    .iter_mut()
+                    .find(|k| Some(&k.id) == entry.existing_id.as_ref())
+                else {
+                    continue;
+                };
+
+                let before = stored.clone();
+                resolve(stored, &entry.incoming, resolution);
+                if *stored == before {
+                    skipped += 1;
+                } else {
+                    updated += 1;
+                }
+            }
+        }
+    }
+
+    ImportResult {
+        keys,
+        added,
+        updated,
+        unchanged,
+        skipped,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key_storage::create_stored_key;
+
+    fn key(name: &str, public_key: &str, private_key: Option<&str>) -> StoredKey {
+        create_stored_key(
+            name.to_string(),
+            public_key.to_string(),
+            private_key.map(str::to_string),
+            None,
+        )
+    }
+
+    fn ids(keys: &[StoredKey]) -> Vec<String> {
+        keys.iter().map(|k| k.id.clone()).collect()
+    }
+
+    #[test]
+    fn reimporting_a_backup_changes_nothing() {
+        let existing = vec![
+            key("work", "age1work", Some("AGE-SECRET-KEY-WORK")),
+            key("alice", "age1alice", None),
+        ];
+        // A backup restored elsewhere carries the same keys under new ids
+        let backup: Vec<StoredKey> = existing
+            .iter()
+            .map(|k| StoredKey {
+                id: uuid::Uuid::new_v4().to_string(),
+                ..k.clone()
+            })
+            .collect();
+
+        let plan = plan_key_import(&existing, &backup);
+        assert_eq!(plan.identical_count, 2);
+        assert_eq!(plan.new_count + plan.conflict_count, 0);
+        assert!(plan.entries.iter().all(|e| e.status == ImportStatus::Identical && e.conflicts.is_empty()));
+
+        let result = apply_key_import(&existing, &backup, ImportResolution::MergeMissing, &HashMap::new());
+        assert_eq!((result.added, result.updated, result.unchanged, result.skipped), (0, 0, 2, 0));
+        assert_eq!(ids(&result.keys), ids(&existing));
+    }
+
+    #[test]
+    fn merge_missing_adds_a_newly_available_private_key() {
+        let mut existing = vec![key("alice", "age1alice", None)];
+        existing[0].use_count = 3;
+        let incoming = vec![
+            key("Alice (laptop)", "age1alice", Some("AGE-SECRET-KEY-ALICE")),
+            key("bob", "age1bob", None),
+        ];
+
+        let plan = plan_key_import(&existing, &incoming);
+        assert_eq!((plan.new_count, plan.identical_count, plan.conflict_count), (1, 0, 1));
+        assert_eq!(plan.entries[0].existing_id.as_ref(), Some(&existing[0].id));
+        assert!(plan.entries[0].conflicts.contains(&ImportConflict::PrivateKeyAvailable));
+        assert!(plan.entries[0].conflicts.contains(&ImportConflict::NameDiffers {
+            existing: "alice".to_string(),
+            incoming: "Alice (laptop)".to_string(),
+        }));
+
+        let result = apply_key_import(&existing, &incoming, ImportResolution::MergeMissing, &HashMap::new());
+        assert_eq!((result.added, result.updated), (1, 1));
+        let alice = &result.keys[0];
+        assert_eq!(alice.id, existing[0].id);
+        assert_eq!(alice.name, "alice");
+        assert_eq!(alice.private_key.as_deref(), Some("AGE-SECRET-KEY-ALICE"));
+        assert_eq!(alice.use_count, 3);
+        assert_eq!(result.keys[1].public_key, "age1bob");
+    }
+
+    #[test]
+    fn overrides_replace_the_default_resolution() {
+        let existing = vec![key("alice", "age1alice", None), key("bob", "age1bob", None)];
+        let incoming = vec![
+            key("Alice", "age1alice", Some("AGE-SECRET-KEY-ALICE")),
+            key("Bob", " age1bob ", None),
+        ];
+        let overrides = HashMap::from([("age1bob".to_string(), ImportResolution::PreferIncoming)]);
+
+        let result = apply_key_import(&existing, &incoming, ImportResolution::SkipConflicts, &overrides);
+        assert_eq!((result.added, result.updated, result.skipped), (0, 1, 1));
+        assert_eq!(result.keys[0].name, "alice");
+        assert!(result.keys[0].private_key.is_none());
+        assert_eq!(result.keys[1].name, "Bob");
+        assert_eq!(result.keys[1].id, existing[1].id);
+    }
+}
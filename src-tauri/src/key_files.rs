@@ -0,0 +1,467 @@
+use crate::key_storage::{create_stored_key, current_timestamp, key_fingerprint, StoredKey};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Version of the JSON public key bundle format
+const PUBLIC_KEY_BUNDLE_VERSION: u32 = 1;
+
+/// Public key entry of a JSON public key bundle
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PublicKeyBundleEntry {
+    pub name: String,
+    pub public_key: String,
+    pub fingerprint: String,
+    pub comment: Option<String>,
+    pub created_at: u64,
+}
+
+/// JSON bundle of public keys, for sharing recipients with other tools
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PublicKeyBundle {
+    pub version: u32,
+    pub exported_at: u64,
+    pub keys: Vec<PublicKeyBundleEntry>,
+}
+
+/// Derive the age recipient (public key) of an `AGE-SECRET-KEY-1...` identity
+pub fn derive_public_from_identity(identity: &str) -> Result<String, String> {
+    let identity = age::x25519::Identity::from_str(identity.trim())
+        .map_err(|e| format!("Invalid age identity: {}", e))?;
+    Ok(identity.to_public().to_string())
+}
+
+/// Check that a line is a recipient age understands: an X25519 recipient or an SSH public key
+fn is_supported_recipient(line: &str) -> bool {
+    if line.starts_with("age1") {
+        return age::x25519::Recipient::from_str(line).is_ok();
+    }
+    line.starts_with("ssh-ed25519 ") || line.starts_with("ssh-rsa ")
+}
+
+/// Parse an identity file as written by `age-keygen`:
+///
+/// ```text
+/// # created: 2024-01-01T00:00:00Z
+/// # public key: age1...
+/// AGE-SECRET-KEY-1...
+/// ```
+///
+/// A file may hold several identities. Comment lines preceding an identity are
+/// kept in `StoredKey.comment`, except the `# public key:` line which is checked
+/// against the derived public key.
+pub fn parse_identity_file(content: &str, name: &str) -> Result<Vec<StoredKey>, String> {
+    let mut keys = Vec::new();
+    let mut comments: Vec<String> = Vec::new();
+    let mut declared_public: Option<String> = None;
+
+    for line in content.lines().map(str::trim) {
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(comment) = line.strip_prefix('#') {
+            let comment = comment.trim();
+            match comment.strip_prefix("public key:") {
+                Some(public_key) => declared_public = Some(public_key.trim().to_string()),
+                None => comments.push(comment.to_string()),
+            }
+            continue;
+        }
+
+        if !line.starts_with("AGE-SECRET-KEY-") {
+            return Err(format!("Unexpected line in identity file: {}", line));
+        }
+
+        let public_key = derive_public_from_identity(line)?;
+        if let Some(declared) = declared_public.take() {
+            if declared != public_key {
+                return Err(format!(
+                    "Public key comment {} does not match the identity it precedes",
+                    declared
+                ));
+            }
+        }
+
+        let comment = if comments.is_empty() {
+            None
+        } else {
+            Some(comments.join("\n"))
+        };
+        comments.clear();
+
+        keys.push(create_stored_key(
+            String::new(),
+            public_key,
+            Some(line.to_string()),
+            comment,
+        ));
+    }
+
+    name_keys(&mut keys, name);
+    Ok(keys)
+}
+
+/// Parse a recipients file as accepted by `age -R`: one recipient per line,
+/// `#` comments and blank lines ignored. A comment line directly above a
+/// recipient, or the comment field of an SSH key, becomes its name.
+pub fn parse_recipients_file(content: &str, name: &str) -> Result<Vec<StoredKey>, String> {
+    let mut keys = Vec::new();
+    let mut comment: Option<String> = None;
+
+    for line in content.lines().map(str::trim) {
+        if line.is_empty() {
+            comment = None;
+            continue;
+        }
+
+        if let Some(text) = line.strip_prefix('#') {
+            comment = Some(text.trim().to_string());
+            continue;
+        }
+
+        if !is_supported_recipient(line) {
+            return Err(format!("Unsupported recipient in recipients file: {}", line));
+        }
+
+        let ssh_comment = if line.starts_with("ssh-") {
+            line.splitn(3, ' ').nth(2).map(|c| c.trim().to_string())
+        } else {
+            None
+        };
+
+        let name = ssh_comment.clone().or_else(|| comment.clone()).unwrap_or_default();
+        keys.push(create_stored_key(
+            name,
+            line.to_string(),
+            None,
+            comment.take().or(ssh_comment),
+        ));
+    }
+
+    name_keys(&mut keys, name);
+    Ok(keys)
+}
+
+/// Give keys without a name one derived from the file they came from
+fn name_keys(keys: &mut [StoredKey], name: &str) {
+    let unnamed = keys.iter().filter(|k| k.name.is_empty()).count();
+    let mut index = 0;
+
+    for key in keys.iter_mut().filter(|k| k.name.is_empty()) {
+        index += 1;
+        key.name = if unnamed > 1 {
+            format!("{} ({})", name, index)
+        } else {
+            name.to_string()
+        };
+    }
+}
+
+/// Import keys from a single `age-keygen` identity file or recipients file
+pub fn import_age_key_file(file_path: &Path) -> Result<Vec<StoredKey>, String> {
+    let content = fs::read_to_string(file_path)
+        .map_err(|e| format!("Failed to read {}: {}", file_path.display(), e))?;
+
+    let name = file_path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "Imported key".to_string());
+
+    if content.contains("AGE-SECRET-KEY-") {
+        parse_identity_file(&content, &name)
+    } else {
+        parse_recipients_file(&content, &name)
+    }
+}
+
+/// Import keys from an identity/recipients file, or from every such file in a
+/// directory. Files in a directory that are not key files are skipped, and
+/// keys are deduplicated by public key.
+pub fn import_age_key_files(path: &str) -> Result<Vec<StoredKey>, String> {
+    let path = Path::new(path);
+
+    let mut keys = if path.is_dir() {
+        let mut entries: Vec<_> = fs::read_dir(path)
+            .map_err(|e| format!("Failed to read directory {}: {}", path.display(), e))?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|p| p.is_file())
+            .collect();
+        entries.sort();
+
+        let mut keys = Vec::new();
+        for entry in entries {
+            if let Ok(found) = import_age_key_file(&entry) {
+                keys.extend(found);
+            }
+        }
+        keys
+    } else {
+        import_age_key_file(path)?
+    };
+
+    // Prefer entries that carry a private key when the same key appears twice
+    keys.sort_by_key(|k| k.private_key.is_none());
+    let mut unique: Vec<StoredKey> = Vec::new();
+    for key in keys {
+        if !unique.iter().any(|k| k.public_key == key.public_key) {
+            unique.push(key);
+        }
+    }
+
+    if unique.is_empty() {
+        return Err(format!("No age identities or recipients found in {}", path.display()));
+    }
+
+    Ok(unique)
+}
+
+fn format_timestamp(timestamp: u64) -> String {
+    chrono::DateTime::<chrono::Utc>::from_timestamp(timestamp as i64, 0)
+        .unwrap_or_default()
+        .to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
+
+/// Turn a stored comment into `#` comment lines
+fn comment_lines(comment: &str) -> String {
+    comment
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| format!("# {}\n", line.trim()))
+        .collect()
+}
+
+/// Render keys with an age private key as an `age-keygen` style identity file.
+/// SSH private keys cannot be mixed into an identity file and are rejected.
+pub fn format_identity_file(keys: &[StoredKey]) -> Result<String, String> {
+    let mut content = String::new();
+
+    for key in keys {
+        let Some(private_key) = key.private_key.as_deref().map(str::trim) else {
+            continue;
+        };
+        if !private_key.starts_with("AGE-SECRET-KEY-") {
+            return Err(format!(
+                "Key \"{}\" is not an age identity and cannot be written to an identity file",
+                key.name
+            ));
+        }
+
+        let comment = key.comment.as_deref().unwrap_or_default();
+        if !comment.contains("created:") {
+            content.push_str(&format!("# created: {}\n", format_timestamp(key.created_at)));
+        }
+        content.push_str(&comment_lines(comment));
+        content.push_str(&format!("# public key: {}\n", key.public_key.trim()));
+        content.push_str(private_key);
+        content.push_str("\n\n");
+    }
+
+    if content.is_empty() {
+        return Err("None of the selected keys has a private key to export".to_string());
+    }
+
+    Ok(content)
+}
+
+/// Render the public keys as a recipients file usable with `age -R`
+pub fn format_recipients_file(keys: &[StoredKey]) -> Result<String, String> {
+    if keys.is_empty() {
+        return Err("No keys selected for export".to_string());
+    }
+
+    Ok(keys
+        .iter()
+        .map(|key| format!("# {}\n{}\n", key.name, key.public_key.trim()))
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+/// Encrypt data with a passphrase as a standard age file (scrypt recipient)
+pub fn encrypt_with_passphrase(passphrase: &str, data: &[u8]) -> Result<Vec<u8>, String> {
+    use std::io::Write;
+
+    let encryptor = age::Encryptor::with_user_passphrase(age::secrecy::Secret::new(passphrase.to_owned()));
+
+    let mut encrypted = Vec::new();
+    let mut writer = encryptor
+        .wrap_output(&mut encrypted)
+        .map_err(|e| format!("Encryption failed: {}", e))?;
+    writer
+        .write_all(data)
+        .map_err(|e| format!("Encryption failed: {}", e))?;
+    writer
+        .finish()
+        .map_err(|e| format!("Encryption failed: {}", e))?;
+
+    Ok(encrypted)
+}
+
+/// Write a file readable only by the current user, as age-keygen does for
+/// secret keys. An existing file is narrowed to that mode before writing.
+pub(crate) fn write_private(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    }
+    file.write_all(data)?;
+    file.sync_all()
+}
+
+/// Export identities to an `age-keygen` style identity file. With a passphrase
+/// the file is itself age-encrypted, readable with `age -d` or as `age -i` input.
+pub fn export_identity_file(
+    keys: &[StoredKey],
+    file_path: &str,
+    passphrase: Option<&str>,
+) -> Result<(), String> {
+    let content = format_identity_file(keys)?;
+
+    let data = match passphrase.filter(|p| !p.is_empty()) {
+        Some(passphrase) => {
+            if passphrase.len() < 4 {
+                return Err("Passphrase must be at least 4 characters".to_string());
+            }
+            encrypt_with_passphrase(passphrase, content.as_bytes())?
+        }
+        None => content.into_bytes(),
+    };
+
+    write_private(Path::new(file_path), &data).map_err(|e| format!("Failed to write identity file: {}", e))
+}
+
+/// Export public keys to a plain recipients file
+pub fn export_recipients_file(keys: &[StoredKey], file_path: &str) -> Result<(), String> {
+    let content = format_recipients_file(keys)?;
+    fs::write(file_path, content).map_err(|e| format!("Failed to write recipients file: {}", e))
+}
+
+/// Export public keys to a JSON public key bundle
+pub fn export_public_key_bundle(keys: &[StoredKey], file_path: &str) -> Result<(), String> {
+    if keys.is_empty() {
+        return Err("No keys selected for export".to_string());
+    }
+
+    let bundle = PublicKeyBundle {
+        version: PUBLIC_KEY_BUNDLE_VERSION,
+        exported_at: current_timestamp(),
+        keys: keys
+            .iter()
+            .map(|key| PublicKeyBundleEntry {
+                name: key.name.clone(),
+                public_key: key.public_key.trim().to_string(),
+                fingerprint: key_fingerprint(&key.public_key),
+                comment: key.comment.clone(),
+                created_at: key.created_at,
+            })
+            .collect(),
+    };
+
+    let json = serde_json::to_string_pretty(&bundle)
+        .map_err(|e| format!("Failed to serialize public key bundle: {}", e))?;
+    fs::write(file_path, json).map_err(|e| format!("Failed to write public key bundle: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use age::secrecy::ExposeSecret;
+
+    fn new_identity() -> (String, String) {
+        let identity = age::x25519::Identity::generate();
+        let secret = identity.to_string().expose_secret().to_string();
+        (secret, identity.to_public().to_string())
+    }
+
+    #[test]
+    fn parses_multiple_identities_with_comments() {
+        let (first_secret, first_public) = new_identity();
+        let (second_secret, second_public) = new_identity();
+        let content = format!(
+            "# created: 2024-01-01T00:00:00Z\n# public key: {}\n{}\n\n# created: 2024-02-01T00:00:00Z\n# laptop\n{}\n",
+            first_public, first_secret, second_secret
+        );
+
+        let keys = parse_identity_file(&content, "keys").unwrap();
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys[0].public_key, first_public);
+        assert_eq!(keys[0].private_key.as_deref(), Some(first_secret.as_str()));
+        assert_eq!(keys[0].comment.as_deref(), Some("created: 2024-01-01T00:00:00Z"));
+        assert_eq!(keys[1].public_key, second_public);
+        assert_eq!(keys[1].comment.as_deref(), Some("created: 2024-02-01T00:00:00Z\nlaptop"));
+        assert_eq!(keys[0].name, "keys (1)");
+        assert_eq!(keys[1].name, "keys (2)");
+    }
+
+    #[test]
+    fn rejects_mismatched_public_key_comment() {
+        let (secret, _) = new_identity();
+        let (_, other_public) = new_identity();
+        let content = format!("# public key: {}\n{}\n", other_public, secret);
+        assert!(parse_identity_file(&content, "keys").is_err());
+    }
+
+    #[test]
+    fn rejects_lines_that_are_not_identities() {
+        let (_, public) = new_identity();
+        assert!(parse_identity_file(&format!("{}\n", public), "keys").is_err());
+    }
+
+    #[test]
+    fn formatted_identities_parse_back() {
+        let (secret, public) = new_identity();
+        let key = create_stored_key("work".to_string(), public.clone(), Some(secret.clone()), None);
+
+        let keys = parse_identity_file(&format_identity_file(&[key]).unwrap(), "work").unwrap();
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys[0].public_key, public);
+        assert_eq!(keys[0].private_key.as_deref(), Some(secret.as_str()));
+    }
+
+    #[test]
+    fn names_recipients_from_comments() {
+        let (_, alice) = new_identity();
+        let (_, unnamed) = new_identity();
+        let content = format!(
+            "# alice\n{}\n\nssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIExample bob@laptop\n\n{}\n",
+            alice, unnamed
+        );
+
+        let keys = parse_recipients_file(&content, "team").unwrap();
+        assert_eq!(keys.len(), 3);
+        assert_eq!(keys[0].name, "alice");
+        assert_eq!(keys[0].public_key, alice);
+        assert_eq!(keys[1].name, "bob@laptop");
+        assert_eq!(keys[1].comment.as_deref(), Some("bob@laptop"));
+        assert!(keys[1].private_key.is_none());
+        assert_eq!(keys[2].name, "team");
+    }
+
+    #[test]
+    fn ssh_comment_takes_precedence_over_line_comment() {
+        let content = "# ops\nssh-rsa AAAAB3NzaC1yc2EAAAADAQABAAABAQExample carol@desk\n";
+        let keys = parse_recipients_file(content, "team").unwrap();
+        assert_eq!(keys[0].name, "carol@desk");
+        assert_eq!(keys[0].comment.as_deref(), Some("ops"));
+    }
+
+    #[test]
+    fn rejects_unsupported_recipients() {
+        assert!(parse_recipients_file("age1notavalidkey\n", "team").is_err());
+        assert!(parse_recipients_file("ssh-dss AAAAB3NzaC1kc3M dave\n", "team").is_err());
+    }
+}
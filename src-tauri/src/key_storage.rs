@@ -1,8 +1,23 @@
+use crate::age::get_app_dir;
+use crate::age_store::{decrypt_age_data, encrypt_to_recipients, is_age_data};
+use crate::contacts::{Contact, RecipientGroup};
+use crate::fs_util::write_atomic;
+use crate::profiles::{get_active_profile_dir, get_profile_dir};
 use serde::{Deserialize, Serialize};
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+/// Number of previous key storage versions kept as backups
+const KEY_STORAGE_BACKUPS: usize = 10;
+
+/// How long to wait for another instance to release the key storage lock
+const KEY_STORAGE_LOCK_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Error returned when the key storage changed on disk since it was loaded.
+/// The frontend matches on this prefix to offer reloading the key storage.
+pub const KEY_STORAGE_CONFLICT_ERROR: &str = "Key storage was modified by another TauriAge instance";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct StoredKey {
     pub id: String,
@@ -11,14 +26,82 @@ pub struct StoredKey {
     pub private_key: Option<String>, // None for public-only keys
     pub comment: Option<String>,
     pub created_at: u64,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub expires_at: Option<u64>, // None for keys that never expire
+    #[serde(default)]
+    pub last_used_at: Option<u64>,
+    #[serde(default)]
+    pub use_count: u64,
+}
+
+impl StoredKey {
+    /// Whether the key has an expiry date that lies in the past
+    pub fn is_expired(&self) -> bool {
+        self.expires_at
+            .map(|expires_at| expires_at <= current_timestamp())
+            .unwrap_or(false)
+    }
+
+    /// Record that the key was just used for an encrypt or decrypt operation
+    pub fn mark_used(&mut self) {
+        self.last_used_at = Some(current_timestamp());
+        self.use_count += 1;
+    }
+}
+
+/// How the key storage file is encrypted
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", tag = "format")]
+pub enum StorageEncryption {
+    /// PBKDF2 + AES-256-GCM container, only readable by TauriAge
+    #[default]
+    Legacy,
+    /// Standard age file encrypted with the passphrase (scrypt), readable with `age -d`
+    AgePassphrase,
+    /// Standard age file encrypted to X25519 or SSH recipients, readable with
+    /// `age -d -i <identity>`. The store is unlocked with a matching identity
+    /// instead of a passphrase.
+    AgeRecipients { recipients: Vec<String> },
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct KeyStorage {
     pub keys: Vec<StoredKey>,
+    #[serde(default)]
+    pub contacts: Vec<Contact>,
+    #[serde(default)]
+    pub groups: Vec<RecipientGroup>,
+    /// Incremented on every write, to detect writes from other app instances
+    #[serde(default)]
+    pub generation: u64,
+    /// Format used the next time the storage is written
+    #[serde(default)]
+    pub encryption: StorageEncryption,
     pub version: u32,
 }
 
+/// Current time as seconds since the Unix epoch
+pub fn current_timestamp() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// Fingerprint of a public key: "SHA256:" followed by the unpadded base64
+/// SHA-256 digest of the key text. For SSH keys this differs from the
+/// `ssh-keygen -l` fingerprint, which hashes the decoded key blob (see
+/// `ssh_keys::ssh_fingerprint`). Stored contact fingerprints use this format.
+pub fn key_fingerprint(public_key: &str) -> String {
+    use base64::{engine::general_purpose::STANDARD_NO_PAD, Engine as _};
+    use sha2::{Digest, Sha256};
+
+    let digest = Sha256::digest(public_key.trim().as_bytes());
+    format!("SHA256:{}", STANDARD_NO_PAD.encode(digest))
+}
+
 /// Create a new StoredKey with current timestamp
 pub fn create_stored_key(
     name: String,
@@ -32,19 +115,57 @@ pub fn create_stored_key(
         public_key,
         private_key,
         comment,
-        created_at: std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_secs(),
+        created_at: current_timestamp(),
+        tags: Vec::new(),
+        expires_at: None,
+        last_used_at: None,
+        use_count: 0,
     }
 }
 
-/// Simple passphrase-based encryption for key storage
-/// Uses a simple PBKDF2 + AES256-GCM construction for demonstration
-/// In production, consider using more robust solutions like age itself for key storage
+/// Build warnings for recipients that belong to expired stored keys
+pub fn expired_recipient_warnings(keys: &[StoredKey], recipients: &[String]) -> Vec<String> {
+    keys.iter()
+        .filter(|key| key.is_expired() && recipients.iter().any(|r| r.trim() == key.public_key))
+        .map(|key| format!("Key \"{}\" expired and should no longer be used for encryption", key.name))
+        .collect()
+}
+
+/// Update usage metadata for every key matched by the predicate.
+/// Returns true when at least one key was updated.
+pub fn record_key_usage<F>(keys: &mut [StoredKey], matches: F) -> bool
+where
+    F: Fn(&StoredKey) -> bool,
+{
+    let mut updated = false;
+    for key in keys.iter_mut().filter(|key| matches(key)) {
+        key.mark_used();
+        updated = true;
+    }
+    updated
+}
+
+/// Carry usage metadata over from a previously stored copy of the same keys,
+/// keeping whichever side has the most recent usage
+pub fn merge_usage_metadata(keys: &mut [StoredKey], previous: &[StoredKey]) {
+    for key in keys.iter_mut() {
+        if let Some(old) = previous.iter().find(|old| old.id == key.id) {
+            key.use_count = key.use_count.max(old.use_count);
+            key.last_used_at = key.last_used_at.max(old.last_used_at);
+        }
+    }
+}
+
+/// Current version of the key storage container contents
+pub const STORAGE_VERSION: u32 = 1;
+
+/// Legacy passphrase-based encryption for key storage
+/// Uses a simple PBKDF2 + AES256-GCM construction with a fixed salt.
+/// Kept for existing stores; `StorageEncryption::AgePassphrase` and
+/// `StorageEncryption::AgeRecipients` write standard age files instead.
 pub fn create_passphrase_encrypted_container(
     passphrase: &str,
-    keys: &[StoredKey],
+    storage: &KeyStorage,
 ) -> Result<Vec<u8>, String> {
     use aes_gcm::{
         aead::{Aead, AeadCore, KeyInit, OsRng},
@@ -53,15 +174,9 @@ pub fn create_passphrase_encrypted_container(
     use pbkdf2::pbkdf2_hmac;
     use sha2::Sha256;
 
-    // Create storage container
-    let storage = KeyStorage {
-        keys: keys.to_vec(),
-        version: 1,
-    };
-
     // Serialize to JSON first
     let json_data =
-        serde_json::to_vec(&storage).map_err(|e| format!("Failed to serialize keys: {}", e))?;
+        serde_json::to_vec(storage).map_err(|e| format!("Failed to serialize keys: {}", e))?;
 
     // Derive key from passphrase using PBKDF2
     let mut key = [0u8; 32];
@@ -84,11 +199,13 @@ pub fn create_passphrase_encrypted_container(
     Ok(result)
 }
 
-/// Decrypt passphrase-encrypted key storage container
-pub fn decrypt_passphrase_container(
-    passphrase: &str,
-    encrypted_data: &[u8],
-) -> Result<Vec<StoredKey>, String> {
+/// Nonce length of the legacy AES-256-GCM container
+pub const LEGACY_NONCE_LEN: usize = 12;
+/// Authentication tag length of the legacy AES-256-GCM container
+pub const LEGACY_TAG_LEN: usize = 16;
+
+/// Decrypt the legacy container to the serialized key storage JSON
+pub fn decrypt_legacy_container(passphrase: &str, encrypted_data: &[u8]) -> Result<Vec<u8>, String> {
     use aes_gcm::{
         aead::{Aead, KeyInit},
         Aes256Gcm, Key,
@@ -96,13 +213,13 @@ pub fn decrypt_passphrase_container(
     use pbkdf2::pbkdf2_hmac;
     use sha2::Sha256;
 
-    if encrypted_data.len() < 12 {
+    if encrypted_data.len() < LEGACY_NONCE_LEN {
         return Err("Encrypted data too short".to_string());
     }
 
     // Extract nonce (first 12 bytes) and ciphertext
-    let nonce_slice = &encrypted_data[0..12];
-    let ciphertext = &encrypted_data[12..];
+    let nonce_slice = &encrypted_data[0..LEGACY_NONCE_LEN];
+    let ciphertext = &encrypted_data[LEGACY_NONCE_LEN..];
 
     // Derive key from passphrase
     let mut key = [0u8; 32];
@@ -111,42 +228,340 @@ pub fn decrypt_passphrase_container(
 
     // Decrypt
     let cipher = Aes256Gcm::new(&aes_key);
-    let decrypted_bytes = cipher
+    cipher
         .decrypt(nonce_slice.into(), ciphertext)
         .map_err(|e| {
             format!(
                 "Decryption failed - incorrect passphrase or corrupted data: {:?}",
                 e
             )
-        })?;
+        })
+}
+
+/// Decrypt passphrase-encrypted key storage container
+pub fn decrypt_passphrase_container(
+    passphrase: &str,
+    encrypted_data: &[u8],
+) -> Result<KeyStorage, String> {
+    let decrypted_bytes = decrypt_legacy_container(passphrase, encrypted_data)?;
 
     // Parse JSON
     let storage: KeyStorage = serde_json::from_slice(&decrypted_bytes)
         .map_err(|e| format!("Failed to parse decrypted data: {}", e))?;
 
-    Ok(storage.keys)
+    Ok(storage)
+}
+
+/// Encrypt key storage in the format selected by `storage.encryption`.
+/// `secret` is the passphrase for passphrase formats and unused when
+/// encrypting to recipients.
+pub fn encrypt_key_storage(secret: &str, storage: &KeyStorage) -> Result<Vec<u8>, String> {
+    match &storage.encryption {
+        StorageEncryption::Legacy => create_passphrase_encrypted_container(secret, storage),
+        StorageEncryption::AgePassphrase => {
+            let json_data = serde_json::to_vec(storage)
+                .map_err(|e| format!("Failed to serialize keys: {}", e))?;
+            crate::key_files::encrypt_with_passphrase(secret, &json_data)
+        }
+        StorageEncryption::AgeRecipients { recipients } => {
+            let json_data = serde_json::to_vec(storage)
+                .map_err(|e| format!("Failed to serialize keys: {}", e))?;
+            encrypt_to_recipients(recipients, &json_data)
+        }
+    }
+}
+
+/// Decrypt key storage in any supported format, detected from the file header.
+/// `secret` is a passphrase, or an identity for stores encrypted to recipients.
+pub fn decrypt_key_storage(secret: &str, encrypted_data: &[u8]) -> Result<KeyStorage, String> {
+    if !is_age_data(encrypted_data) {
+        return decrypt_passphrase_container(secret, encrypted_data);
+    }
+
+    let json_data = decrypt_age_data(secret, encrypted_data)?;
+    serde_json::from_slice(&json_data).map_err(|e| format!("Failed to parse decrypted data: {}", e))
+}
+
+/// Switch the encryption format of a key storage and check that `secret`
+/// opens the result, so a store is never written in a form the user cannot unlock
+pub fn reencrypt_key_storage(
+    storage: &KeyStorage,
+    encryption: StorageEncryption,
+    secret: &str,
+) -> Result<KeyStorage, String> {
+    let mut converted = storage.clone();
+    converted.encryption = encryption;
+
+    match &converted.encryption {
+        StorageEncryption::Legacy | StorageEncryption::AgePassphrase if secret.len() < 4 => {
+            return Err("Passphrase must be at least 4 characters".to_string())
+        }
+        StorageEncryption::AgeRecipients { recipients } if recipients.is_empty() => {
+            return Err("At least one recipient is required".to_string())
+        }
+        _ => {}
+    }
+
+    let encrypted = encrypt_key_storage(secret, &converted)?;
+    decrypt_key_storage(secret, &encrypted)
+        .map_err(|e| format!("The key storage could not be opened after re-encryption: {}", e))?;
+
+    Ok(converted)
 }
 
-/// Save encrypted key storage to a file
+/// Save encrypted key storage to a file, keeping the previous version as a backup
 pub fn save_key_storage(
     passphrase: &str,
-    keys: &[StoredKey],
+    storage: &KeyStorage,
     file_path: &str,
 ) -> Result<(), String> {
-    let encrypted_data = create_passphrase_encrypted_container(passphrase, keys)?;
+    let encrypted_data = encrypt_key_storage(passphrase, storage)?;
 
-    fs::write(file_path, encrypted_data)
+    backup_key_storage(file_path)?;
+    write_atomic(Path::new(file_path), &encrypted_data)
         .map_err(|e| format!("Failed to write key storage file: {}", e))?;
 
     Ok(())
 }
 
+/// Save encrypted key storage without rotating backups, for frequent
+/// metadata-only updates such as key usage counters
+pub fn update_key_storage(
+    passphrase: &str,
+    storage: &KeyStorage,
+    file_path: &str,
+) -> Result<(), String> {
+    let encrypted_data = encrypt_key_storage(passphrase, storage)?;
+
+    write_atomic(Path::new(file_path), &encrypted_data)
+        .map_err(|e| format!("Failed to write key storage file: {}", e))
+}
+
+/// Advisory lock on a key storage file, held through a `<file>.lock` sidecar.
+/// The lock is released when the guard is dropped.
+pub struct KeyStorageLock {
+    _file: fs::File,
+}
+
+/// Take the inter-process lock of a key storage file, waiting up to
+/// `KEY_STORAGE_LOCK_TIMEOUT` for another instance to finish writing
+pub fn lock_key_storage(file_path: &str) -> Result<KeyStorageLock, String> {
+    let lock_path = format!("{}.lock", file_path);
+    let file = fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&lock_path)
+        .map_err(|e| format!("Failed to open key storage lock file: {}", e))?;
+
+    let deadline = std::time::Instant::now() + KEY_STORAGE_LOCK_TIMEOUT;
+    loop {
+        match file.try_lock() {
+            Ok(()) => return Ok(KeyStorageLock { _file: file }),
+            Err(fs::TryLockError::WouldBlock) if std::time::Instant::now() < deadline => {
+                std::thread::sleep(std::time::Duration::from_millis(50));
+            }
+            Err(fs::TryLockError::WouldBlock) => {
+                return Err("Key storage is locked by another TauriAge instance".to_string())
+            }
+            Err(fs::TryLockError::Error(e)) => {
+                return Err(format!("Failed to lock key storage: {}", e))
+            }
+        }
+    }
+}
+
+/// Write key storage under the inter-process lock, refusing the write when
+/// the generation on disk is not the one the caller loaded.
+///
+/// `expected_generation` is None when the caller has not loaded the file
+/// (e.g. when creating a new store), so an existing file is a conflict.
+/// `disk_passphrase` is the passphrase the file on disk is encrypted with,
+/// which differs from `passphrase` when the passphrase is being changed.
+/// Metadata-only updates pass `keep_backup: false`.
+pub fn commit_key_storage(
+    passphrase: &str,
+    storage: &mut KeyStorage,
+    file_path: &str,
+    expected_generation: Option<u64>,
+    disk_passphrase: &str,
+    keep_backup: bool,
+) -> Result<(), String> {
+    let _lock = lock_key_storage(file_path)?;
+
+    // A store that cannot be opened is reported as such, not as a conflict
+    let on_disk_generation = if key_storage_exists(file_path) {
+        Some(load_key_storage(disk_passphrase, file_path)?.generation)
+    } else {
+        None
+    };
+
+    let conflict = match (expected_generation, on_disk_generation) {
+        (None, Some(_)) => true,
+        (Some(expected), Some(on_disk)) => expected != on_disk,
+        (_, None) => false,
+    };
+    if conflict {
+        return Err(format!(
+            "{}. Reload the key storage and apply your changes again.",
+            KEY_STORAGE_CONFLICT_ERROR
+        ));
+    }
+
+    storage.generation = on_disk_generation.or(expected_generation).unwrap_or(0) + 1;
+
+    if keep_backup {
+        save_key_storage(passphrase, storage, file_path)
+    } else {
+        update_key_storage(passphrase, storage, file_path)
+    }
+}
+
+/// Re-encrypt a passphrase-protected key storage file with a new passphrase,
+/// keeping its format. Nothing is written when the file does not exist yet.
+pub fn change_key_storage_passphrase(
+    file_path: &str,
+    current_passphrase: &str,
+    new_passphrase: &str,
+) -> Result<(), String> {
+    if !key_storage_exists(file_path) {
+        return Ok(());
+    }
+
+    let storage = load_key_storage(current_passphrase, file_path)?;
+    if let StorageEncryption::AgeRecipients { .. } = storage.encryption {
+        return Err("This key storage is encrypted to recipients and is not unlocked with a passphrase".to_string());
+    }
+
+    let mut updated = reencrypt_key_storage(&storage, storage.encryption.clone(), new_passphrase)?;
+    commit_key_storage(
+        new_passphrase,
+        &mut updated,
+        file_path,
+        Some(storage.generation),
+        current_passphrase,
+        true,
+    )
+}
+
+/// A previous version of the key storage file
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyStorageBackup {
+    pub path: String,
+    pub created_at: u64,
+    pub size: u64,
+}
+
+/// Directory holding the backups of a key storage file
+fn key_storage_backup_dir(file_path: &Path) -> PathBuf {
+    file_path
+        .parent()
+        .unwrap_or_else(|| Path::new("."))
+        .join("backups")
+}
+
+/// Backup file name prefix for a key storage file, e.g. "keys.enc."
+fn key_storage_backup_prefix(file_path: &Path) -> String {
+    let file_name = file_path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| "keys.enc".to_string());
+    format!("{}.", file_name)
+}
+
+/// Copy the current key storage file into the backups directory and drop
+/// the oldest backups beyond `KEY_STORAGE_BACKUPS`
+pub fn backup_key_storage(file_path: &str) -> Result<Option<PathBuf>, String> {
+    let path = Path::new(file_path);
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let backup_dir = key_storage_backup_dir(path);
+    fs::create_dir_all(&backup_dir)
+        .map_err(|e| format!("Could not create backup directory: {}", e))?;
+
+    let timestamp = chrono::Utc::now().format("%Y%m%d-%H%M%S-%3f");
+    let backup_path = backup_dir.join(format!("{}{}.bak", key_storage_backup_prefix(path), timestamp));
+
+    let data = fs::read(path).map_err(|e| format!("Failed to read key storage file: {}", e))?;
+    write_atomic(&backup_path, &data)
+        .map_err(|e| format!("Failed to write key storage backup: {}", e))?;
+
+    for old in list_key_storage_backups(file_path)?.iter().skip(KEY_STORAGE_BACKUPS) {
+        let _ = fs::remove_file(&old.path);
+    }
+
+    Ok(Some(backup_path))
+}
+
+/// List backups of a key storage file, newest first
+pub fn list_key_storage_backups(file_path: &str) -> Result<Vec<KeyStorageBackup>, String> {
+    let path = Path::new(file_path);
+    let backup_dir = key_storage_backup_dir(path);
+    if !backup_dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let prefix = key_storage_backup_prefix(path);
+    let entries = fs::read_dir(&backup_dir)
+        .map_err(|e| format!("Failed to read backup directory: {}", e))?;
+
+    let mut backups: Vec<(String, KeyStorageBackup)> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !name.starts_with(&prefix) || !name.ends_with(".bak") {
+                return None;
+            }
+            let metadata = entry.metadata().ok()?;
+            let created_at = metadata
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|d| d.as_secs())
+                .unwrap_or_default();
+            Some((
+                name,
+                KeyStorageBackup {
+                    path: entry.path().to_string_lossy().to_string(),
+                    created_at,
+                    size: metadata.len(),
+                },
+            ))
+        })
+        .collect();
+
+    // Backup names embed a sortable timestamp
+    backups.sort_by(|a, b| b.0.cmp(&a.0));
+    Ok(backups.into_iter().map(|(_, backup)| backup).collect())
+}
+
+/// Replace the key storage file with one of its backups. The current file is
+/// backed up first, so a restore can itself be undone.
+pub fn restore_key_storage_backup(file_path: &str, backup_path: &str) -> Result<(), String> {
+    let is_known_backup = list_key_storage_backups(file_path)?
+        .iter()
+        .any(|backup| backup.path == backup_path);
+    if !is_known_backup {
+        return Err(format!("Not a backup of this key storage: {}", backup_path));
+    }
+
+    let data = fs::read(backup_path).map_err(|e| format!("Failed to read backup: {}", e))?;
+
+    let _lock = lock_key_storage(file_path)?;
+    backup_key_storage(file_path)?;
+    write_atomic(Path::new(file_path), &data)
+        .map_err(|e| format!("Failed to restore key storage file: {}", e))
+}
+
 /// Load encrypted key storage from a file
-pub fn load_key_storage(passphrase: &str, file_path: &str) -> Result<Vec<StoredKey>, String> {
+pub fn load_key_storage(passphrase: &str, file_path: &str) -> Result<KeyStorage, String> {
     let encrypted_data =
         fs::read(file_path).map_err(|e| format!("Failed to read key storage file: {}", e))?;
 
-    decrypt_passphrase_container(passphrase, &encrypted_data)
+    decrypt_key_storage(passphrase, &encrypted_data)
 }
 
 /// Check if a key storage file exists
@@ -154,26 +569,68 @@ pub fn key_storage_exists(file_path: &str) -> bool {
     Path::new(file_path).exists()
 }
 
-/// Get default key storage path (in user config directory)
-pub fn get_default_key_storage_path() -> Result<String, String> {
-    let config_dir = dirs::config_dir().ok_or("Could not determine config directory")?;
+/// Marker file next to the executable that turns on portable mode
+const PORTABLE_MARKER_FILE: &str = "TauriAge.portable";
+/// Environment variable that turns portable mode on ("1"/"true") or off ("0"/"false")
+const PORTABLE_ENV_VAR: &str = "TAURIAGE_PORTABLE";
+/// Directory next to the executable holding all data in portable mode
+const PORTABLE_DATA_DIR: &str = "TauriAgeData";
 
-    let age_dir = config_dir.join("TauriAge");
-    std::fs::create_dir_all(&age_dir)
-        .map_err(|e| format!("Could not create config directory: {}", e))?;
+/// Whether the app runs in portable mode, keeping its configuration next to
+/// the executable (e.g. on a USB stick) instead of the user config directory.
+/// The environment variable takes precedence over the marker file.
+pub fn is_portable_mode() -> bool {
+    if let Ok(value) = std::env::var(PORTABLE_ENV_VAR) {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "1" | "true" | "yes" => return true,
+            "0" | "false" | "no" => return false,
+            _ => {}
+        }
+    }
 
-    Ok(age_dir.join("keys.enc").to_string_lossy().to_string())
+    get_app_dir()
+        .map(|dir| dir.join(PORTABLE_MARKER_FILE).exists())
+        .unwrap_or(false)
 }
 
-/// Get passphrase config file path
-pub fn get_passphrase_file_path() -> Result<String, String> {
-    let config_dir = dirs::config_dir().ok_or("Could not determine config directory")?;
+/// Get the app config directory: next to the executable in portable mode,
+/// otherwise in the user config directory
+pub fn get_app_config_dir() -> Result<PathBuf, String> {
+    let age_dir = if is_portable_mode() {
+        get_app_dir()?.join(PORTABLE_DATA_DIR)
+    } else {
+        let config_dir = dirs::config_dir().ok_or("Could not determine config directory")?;
+        config_dir.join("TauriAge")
+    };
 
-    let age_dir = config_dir.join("TauriAge");
     std::fs::create_dir_all(&age_dir)
         .map_err(|e| format!("Could not create config directory: {}", e))?;
 
-    Ok(age_dir.join(".passphrase").to_string_lossy().to_string())
+    Ok(age_dir)
+}
+
+/// Get default key storage path (in the active profile directory)
+pub fn get_default_key_storage_path() -> Result<String, String> {
+    let profile_dir = get_active_profile_dir()?;
+    Ok(profile_dir.join("keys.enc").to_string_lossy().to_string())
+}
+
+/// Get the key storage path of any profile
+pub fn get_profile_key_storage_path(profile_id: &str) -> Result<String, String> {
+    let profile_dir = get_profile_dir(profile_id)?;
+    Ok(profile_dir.join("keys.enc").to_string_lossy().to_string())
+}
+
+/// Get passphrase config file path (in the active profile directory)
+pub fn get_passphrase_file_path() -> Result<String, String> {
+    let profile_dir = get_active_profile_dir()?;
+    Ok(profile_dir.join(".passphrase").to_string_lossy().to_string())
+}
+
+/// Get the passphrase config file path of any profile
+pub fn get_profile_passphrase_file_path(profile_id: &str) -> Result<String, String> {
+    let profile_dir = get_profile_dir(profile_id)?;
+    Ok(profile_dir.join(".passphrase").to_string_lossy().to_string())
 }
 
 /// Generate a strong passphrase based on username and random component
@@ -222,55 +679,197 @@ pub fn get_or_create_passphrase() -> Result<String, String> {
 }
 
 // Magic bytes for export file format: "TAKI" = TauriAge Key Import
-const EXPORT_MAGIC: &[u8; 4] = b"TAKI";
-const EXPORT_VERSION: u32 = 1;
+pub(crate) const EXPORT_MAGIC: &[u8; 4] = b"TAKI";
+const EXPORT_VERSION_V1: u32 = 1;
+const EXPORT_VERSION: u32 = 2;
+
+/// PBKDF2 rounds used for new exports; imports use the value from the header
+#[cfg(not(test))]
+const EXPORT_PBKDF2_ITERATIONS: u32 = 600_000;
+/// Tests export with the fewest accepted rounds, as unoptimized builds are slow
+#[cfg(test)]
+const EXPORT_PBKDF2_ITERATIONS: u32 = EXPORT_MIN_ITERATIONS;
+/// Bounds on header-provided PBKDF2 rounds, so a crafted file cannot stall the app
+const EXPORT_MIN_ITERATIONS: u32 = 100_000;
+const EXPORT_MAX_ITERATIONS: u32 = 10_000_000;
+/// Upper bound for the unencrypted header of a v2 export
+const EXPORT_MAX_HEADER_LEN: usize = 1024 * 1024;
+
+/// Key derivation parameters stored in a TAKI v2 header
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportKdf {
+    pub algorithm: String,
+    pub iterations: u32,
+    pub salt: String, // base64
+}
+
+/// Application that produced an export
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ExporterInfo {
+    pub app: String,
+    pub version: String,
+    pub platform: String,
+}
+
+/// Unencrypted description of one exported key
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportManifestEntry {
+    pub name: String,
+    pub public_key: String,
+    pub fingerprint: String,
+    pub has_private_key: bool,
+}
+
+/// Unencrypted header of a TAKI v2 export. It is authenticated as associated
+/// data of the encrypted payload, so it cannot be altered without detection.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportHeader {
+    pub kdf: ExportKdf,
+    pub exporter: ExporterInfo,
+    pub created_at: u64,
+    pub public_only: bool,
+    pub manifest: Vec<ExportManifestEntry>,
+}
+
+/// What can be learned about an export file before entering the passphrase
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportPreview {
+    pub version: u32,
+    pub header: Option<ExportHeader>, // None for v1 exports, which have no manifest
+}
+
+/// Which keys to export and how
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportOptions {
+    /// Export only the keys with these ids; all keys when None
+    #[serde(default)]
+    pub key_ids: Option<Vec<String>>,
+    /// Strip private keys, producing a file that is safe to share
+    #[serde(default)]
+    pub public_only: bool,
+}
+
+fn derive_export_key(passphrase: &str, salt: &[u8], iterations: u32) -> [u8; 32] {
+    use pbkdf2::pbkdf2_hmac;
+    use sha2::Sha256;
+
+    let mut key = [0u8; 32];
+    pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), salt, iterations, &mut key);
+    key
+}
 
 /// Export keys to a file with a user-provided passphrase
-/// File format: [4 bytes magic "TAKI"][4 bytes version][12 bytes nonce][encrypted data]
+/// File format (v2): [4 bytes magic "TAKI"][4 bytes version][4 bytes header length]
+/// [header JSON][12 bytes nonce][encrypted data]
 pub fn export_keys_to_file(
     passphrase: &str,
     keys: &[StoredKey],
     file_path: &str,
+    options: &ExportOptions,
 ) -> Result<(), String> {
     use aes_gcm::{
-        aead::{Aead, AeadCore, KeyInit, OsRng},
+        aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
         Aes256Gcm, Key,
     };
-    use pbkdf2::pbkdf2_hmac;
-    use sha2::Sha256;
+    use base64::{engine::general_purpose::STANDARD, Engine as _};
+    use rand::RngCore;
 
     if passphrase.len() < 4 {
         return Err("Passphrase must be at least 4 characters".to_string());
     }
 
+    // Select the keys to export
+    let mut selected: Vec<StoredKey> = keys
+        .iter()
+        .filter(|key| {
+            options
+                .key_ids
+                .as_ref()
+                .map(|ids| ids.contains(&key.id))
+                .unwrap_or(true)
+        })
+        .cloned()
+        .collect();
+    if selected.is_empty() {
+        return Err("No keys selected for export".to_string());
+    }
+    if options.public_only {
+        for key in selected.iter_mut() {
+            key.private_key = None;
+        }
+    }
+
+    let mut salt = [0u8; 16];
+    OsRng.fill_bytes(&mut salt);
+
+    let header = ExportHeader {
+        kdf: ExportKdf {
+            algorithm: "pbkdf2-sha256".to_string(),
+            iterations: EXPORT_PBKDF2_ITERATIONS,
+            salt: STANDARD.encode(salt),
+        },
+        exporter: ExporterInfo {
+            app: "TauriAge".to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            platform: std::env::consts::OS.to_string(),
+        },
+        created_at: current_timestamp(),
+        public_only: options.public_only,
+        manifest: selected
+            .iter()
+            .map(|key| ExportManifestEntry {
+                name: key.name.clone(),
+                public_key: key.public_key.clone(),
+                fingerprint: key_fingerprint(&key.public_key),
+                has_private_key: key.private_key.is_some(),
+            })
+            .collect(),
+    };
+    let header_json = serde_json::to_vec(&header)
+        .map_err(|e| format!("Failed to serialize export header: {}", e))?;
+
     // Create storage container
     let storage = KeyStorage {
-        keys: keys.to_vec(),
+        keys: selected,
         version: EXPORT_VERSION,
+        ..Default::default()
     };
 
     // Serialize to JSON
     let json_data =
         serde_json::to_vec(&storage).map_err(|e| format!("Failed to serialize keys: {}", e))?;
 
-    // Derive key from passphrase using PBKDF2 with a different salt for exports
-    let mut key = [0u8; 32];
-    pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), b"tauriage-export-salt", 100_000, &mut key);
+    // Derive key from passphrase using PBKDF2 with a random per-export salt
+    let key = derive_export_key(passphrase, &salt, EXPORT_PBKDF2_ITERATIONS);
     let aes_key = Key::<Aes256Gcm>::from_slice(&key);
 
     // Generate nonce
     let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
 
-    // Encrypt the data
-    let cipher = Aes256Gcm::new(&aes_key);
+    // Encrypt the data, authenticating the header alongside it
+    let cipher = Aes256Gcm::new(aes_key);
     let ciphertext = cipher
-        .encrypt(&nonce, json_data.as_ref())
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: &json_data,
+                aad: &header_json,
+            },
+        )
         .map_err(|e| format!("Encryption failed: {:?}", e))?;
 
-    // Build the export file: magic + version + nonce + ciphertext
+    // Build the export file: magic + version + header length + header + nonce + ciphertext
     let mut result = Vec::new();
     result.extend_from_slice(EXPORT_MAGIC);
     result.extend_from_slice(&EXPORT_VERSION.to_le_bytes());
+    result.extend_from_slice(&(header_json.len() as u32).to_le_bytes());
+    result.extend_from_slice(&header_json);
     result.extend_from_slice(&nonce);
     result.extend(ciphertext);
 
@@ -280,55 +879,363 @@ pub fn export_keys_to_file(
     Ok(())
 }
 
+/// Split an export file into its version and the data following the version field
+fn read_export_version(data: &[u8]) -> Result<(u32, &[u8]), String> {
+    if data.len() < 8 {
+        return Err("Export file is too small or corrupted".to_string());
+    }
+
+    // Verify magic bytes
+    if &data[0..4] != EXPORT_MAGIC {
+        return Err("Invalid export file format (wrong magic bytes)".to_string());
+    }
+
+    let version = u32::from_le_bytes([data[4], data[5], data[6], data[7]]);
+    Ok((version, &data[8..]))
+}
+
+/// Parse the header of a v2 export, returning it with its raw bytes and the remaining data
+fn read_export_header(data: &[u8]) -> Result<(ExportHeader, &[u8], &[u8]), String> {
+    if data.len() < 4 {
+        return Err("Export file is too small or corrupted".to_string());
+    }
+
+    let header_len = u32::from_le_bytes([data[0], data[1], data[2], data[3]]) as usize;
+    if header_len > EXPORT_MAX_HEADER_LEN || data.len() < 4 + header_len {
+        return Err("Export file header is truncated or corrupted".to_string());
+    }
+
+    let header_json = &data[4..4 + header_len];
+    let header: ExportHeader = serde_json::from_slice(header_json)
+        .map_err(|e| format!("Failed to parse export file header: {}", e))?;
+
+    Ok((header, header_json, &data[4 + header_len..]))
+}
+
+/// Read the unencrypted manifest of an export file without the passphrase
+pub fn preview_export_file(file_path: &str) -> Result<ExportPreview, String> {
+    let data = fs::read(file_path)
+        .map_err(|e| format!("Failed to read export file: {}", e))?;
+
+    let (version, rest) = read_export_version(&data)?;
+    match version {
+        EXPORT_VERSION_V1 => Ok(ExportPreview { version, header: None }),
+        EXPORT_VERSION => {
+            let (header, _, _) = read_export_header(rest)?;
+            Ok(ExportPreview {
+                version,
+                header: Some(header),
+            })
+        }
+        _ => Err(format!("Unsupported export file version: {}", version)),
+    }
+}
+
 /// Import keys from an exported file using a user-provided passphrase
 pub fn import_keys_from_file(
     passphrase: &str,
     file_path: &str,
 ) -> Result<Vec<StoredKey>, String> {
+    let data = fs::read(file_path)
+        .map_err(|e| format!("Failed to read export file: {}", e))?;
+
+    let (version, rest) = read_export_version(&data)?;
+    let decrypted_bytes = match version {
+        EXPORT_VERSION_V1 => decrypt_export_v1(passphrase, rest)?,
+        EXPORT_VERSION => decrypt_export_v2(passphrase, rest)?,
+        _ => return Err(format!("Unsupported export file version: {}", version)),
+    };
+
+    // Parse JSON
+    let storage: KeyStorage = serde_json::from_slice(&decrypted_bytes)
+        .map_err(|e| format!("Failed to parse decrypted data: {}", e))?;
+
+    Ok(storage.keys)
+}
+
+/// Decrypt a v1 export: [12 bytes nonce][encrypted data] with a fixed salt
+fn decrypt_export_v1(passphrase: &str, data: &[u8]) -> Result<Vec<u8>, String> {
     use aes_gcm::{
         aead::{Aead, KeyInit},
         Aes256Gcm, Key,
     };
-    use pbkdf2::pbkdf2_hmac;
-    use sha2::Sha256;
 
-    let data = fs::read(file_path)
-        .map_err(|e| format!("Failed to read export file: {}", e))?;
-
-    // Minimum size: 4 (magic) + 4 (version) + 12 (nonce) + 16 (min ciphertext with tag)
-    if data.len() < 36 {
+    // Minimum size: 12 (nonce) + 16 (min ciphertext with tag)
+    if data.len() < 28 {
         return Err("Export file is too small or corrupted".to_string());
     }
 
-    // Verify magic bytes
-    if &data[0..4] != EXPORT_MAGIC {
-        return Err("Invalid export file format (wrong magic bytes)".to_string());
-    }
-
-    // Read version
-    let version = u32::from_le_bytes([data[4], data[5], data[6], data[7]]);
-    if version != EXPORT_VERSION {
-        return Err(format!("Unsupported export file version: {}", version));
-    }
-
     // Extract nonce and ciphertext
-    let nonce_slice = &data[8..20];
-    let ciphertext = &data[20..];
+    let nonce_slice = &data[0..12];
+    let ciphertext = &data[12..];
 
     // Derive key from passphrase
-    let mut key = [0u8; 32];
-    pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), b"tauriage-export-salt", 100_000, &mut key);
+    let key = derive_export_key(passphrase, b"tauriage-export-salt", 100_000);
     let aes_key = Key::<Aes256Gcm>::from_slice(&key);
 
     // Decrypt
-    let cipher = Aes256Gcm::new(&aes_key);
-    let decrypted_bytes = cipher
+    let cipher = Aes256Gcm::new(aes_key);
+    cipher
         .decrypt(nonce_slice.into(), ciphertext)
-        .map_err(|_| "Decryption failed - incorrect passphrase or corrupted file".to_string())?;
+        .map_err(|_| "Decryption failed - incorrect passphrase or corrupted file".to_string())
+}
 
-    // Parse JSON
-    let storage: KeyStorage = serde_json::from_slice(&decrypted_bytes)
-        .map_err(|e| format!("Failed to parse decrypted data: {}", e))?;
+/// Decrypt a v2 export: [4 bytes header length][header JSON][12 bytes nonce][encrypted data]
+fn decrypt_export_v2(passphrase: &str, data: &[u8]) -> Result<Vec<u8>, String> {
+    use aes_gcm::{
+        aead::{Aead, KeyInit, Payload},
+        Aes256Gcm, Key,
+    };
+    use base64::{engine::general_purpose::STANDARD, Engine as _};
 
-    Ok(storage.keys)
+    let (header, header_json, rest) = read_export_header(data)?;
+
+    if header.kdf.algorithm != "pbkdf2-sha256" {
+        return Err(format!("Unsupported key derivation: {}", header.kdf.algorithm));
+    }
+    if !(EXPORT_MIN_ITERATIONS..=EXPORT_MAX_ITERATIONS).contains(&header.kdf.iterations) {
+        return Err(format!(
+            "Unsupported key derivation iteration count: {}",
+            header.kdf.iterations
+        ));
+    }
+    let salt = STANDARD
+        .decode(&header.kdf.salt)
+        .map_err(|e| format!("Invalid salt in export file header: {}", e))?;
+
+    // Minimum size: 12 (nonce) + 16 (min ciphertext with tag)
+    if rest.len() < 28 {
+        return Err("Export file is too small or corrupted".to_string());
+    }
+
+    let nonce_slice = &rest[0..12];
+    let ciphertext = &rest[12..];
+
+    let key = derive_export_key(passphrase, &salt, header.kdf.iterations);
+    let aes_key = Key::<Aes256Gcm>::from_slice(&key);
+
+    let cipher = Aes256Gcm::new(aes_key);
+    cipher
+        .decrypt(
+            nonce_slice.into(),
+            Payload {
+                msg: ciphertext,
+                aad: header_json,
+            },
+        )
+        .map_err(|_| "Decryption failed - incorrect passphrase or corrupted file".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("tauriage-{}-{}", name, uuid::Uuid::new_v4().simple()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn commit_refuses_to_replace_an_unloaded_store() {
+        let dir = temp_dir("commit");
+        let path = dir.join("keys.enc").to_string_lossy().to_string();
+
+        let mut first = KeyStorage::default();
+        commit_key_storage("secret", &mut first, &path, None, "secret", true).unwrap();
+        assert_eq!(first.generation, 1);
+
+        let mut second = KeyStorage::default();
+        let error = commit_key_storage("secret", &mut second, &path, None, "secret", true).unwrap_err();
+        assert!(error.starts_with(KEY_STORAGE_CONFLICT_ERROR));
+
+        let error = commit_key_storage("secret", &mut second, &path, Some(0), "secret", true).unwrap_err();
+        assert!(error.starts_with(KEY_STORAGE_CONFLICT_ERROR));
+
+        commit_key_storage("secret", &mut first, &path, Some(1), "secret", true).unwrap();
+        assert_eq!(load_key_storage("secret", &path).unwrap().generation, 2);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn commit_reports_a_store_it_cannot_open() {
+        let dir = temp_dir("commit");
+        let path = dir.join("keys.enc").to_string_lossy().to_string();
+
+        let mut storage = KeyStorage::default();
+        commit_key_storage("secret", &mut storage, &path, None, "secret", true).unwrap();
+
+        let error = commit_key_storage("other", &mut storage, &path, Some(1), "wrong", true).unwrap_err();
+        assert!(!error.starts_with(KEY_STORAGE_CONFLICT_ERROR));
+        assert!(load_key_storage("secret", &path).is_ok());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn changing_the_passphrase_reencrypts_the_store() {
+        let dir = temp_dir("passphrase");
+        let path = dir.join("keys.enc").to_string_lossy().to_string();
+
+        change_key_storage_passphrase(&path, "old secret", "new secret").unwrap();
+        assert!(!key_storage_exists(&path));
+
+        let mut storage = KeyStorage::default();
+        commit_key_storage("old secret", &mut storage, &path, None, "old secret", true).unwrap();
+
+        assert!(change_key_storage_passphrase(&path, "wrong", "new secret").is_err());
+        assert!(change_key_storage_passphrase(&path, "old secret", "new").is_err());
+        change_key_storage_passphrase(&path, "old secret", "new secret").unwrap();
+        assert!(load_key_storage("old secret", &path).is_err());
+        assert_eq!(load_key_storage("new secret", &path).unwrap().generation, 2);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn export_test_keys() -> Vec<StoredKey> {
+        vec![
+            create_stored_key(
+                "work".to_string(),
+                "age1workpublickey".to_string(),
+                Some("AGE-SECRET-KEY-WORK".to_string()),
+                None,
+            ),
+            create_stored_key("alice".to_string(), "age1alicepublickey".to_string(), None, None),
+        ]
+    }
+
+    /// Rebuild a v2 export around a modified header, keeping the original payload
+    fn replace_export_header(data: &[u8], header: &ExportHeader) -> Vec<u8> {
+        let (_, _, payload) = read_export_header(&data[8..]).unwrap();
+        let header_json = serde_json::to_vec(header).unwrap();
+
+        let mut result = data[..8].to_vec();
+        result.extend_from_slice(&(header_json.len() as u32).to_le_bytes());
+        result.extend_from_slice(&header_json);
+        result.extend_from_slice(payload);
+        result
+    }
+
+    #[test]
+    fn export_v2_round_trip() {
+        let dir = temp_dir("export");
+        let path = dir.join("keys.taki").to_string_lossy().to_string();
+        let keys = export_test_keys();
+
+        export_keys_to_file("passphrase", &keys, &path, &ExportOptions::default()).unwrap();
+        let imported = import_keys_from_file("passphrase", &path).unwrap();
+        assert_eq!(imported.len(), 2);
+        assert_eq!(imported[0].id, keys[0].id);
+        assert_eq!(imported[0].private_key, keys[0].private_key);
+        assert_eq!(imported[1].public_key, keys[1].public_key);
+        assert!(import_keys_from_file("wrong passphrase", &path).is_err());
+
+        let options = ExportOptions {
+            key_ids: Some(vec![keys[0].id.clone()]),
+            public_only: true,
+        };
+        export_keys_to_file("passphrase", &keys, &path, &options).unwrap();
+        let imported = import_keys_from_file("passphrase", &path).unwrap();
+        assert_eq!(imported.len(), 1);
+        assert_eq!(imported[0].public_key, keys[0].public_key);
+        assert!(imported[0].private_key.is_none());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn imports_v1_exports() {
+        use aes_gcm::{
+            aead::{Aead, AeadCore, KeyInit, OsRng},
+            Aes256Gcm, Key,
+        };
+
+        let dir = temp_dir("export");
+        let path = dir.join("keys.taki").to_string_lossy().to_string();
+        let storage = KeyStorage {
+            keys: export_test_keys(),
+            version: EXPORT_VERSION_V1,
+            ..Default::default()
+        };
+
+        // v1 layout: magic + version + nonce + ciphertext, with a fixed salt
+        let key = derive_export_key("passphrase", b"tauriage-export-salt", 100_000);
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = cipher.encrypt(&nonce, serde_json::to_vec(&storage).unwrap().as_ref()).unwrap();
+        let mut data = EXPORT_MAGIC.to_vec();
+        data.extend_from_slice(&EXPORT_VERSION_V1.to_le_bytes());
+        data.extend_from_slice(&nonce);
+        data.extend(ciphertext);
+        fs::write(&path, data).unwrap();
+
+        let preview = preview_export_file(&path).unwrap();
+        assert_eq!(preview.version, EXPORT_VERSION_V1);
+        assert!(preview.header.is_none());
+
+        let imported = import_keys_from_file("passphrase", &path).unwrap();
+        assert_eq!(imported.len(), 2);
+        assert_eq!(imported[0].private_key.as_deref(), Some("AGE-SECRET-KEY-WORK"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn preview_reads_the_manifest_without_the_passphrase() {
+        let dir = temp_dir("export");
+        let path = dir.join("keys.taki").to_string_lossy().to_string();
+        export_keys_to_file("passphrase", &export_test_keys(), &path, &ExportOptions::default()).unwrap();
+
+        let preview = preview_export_file(&path).unwrap();
+        assert_eq!(preview.version, EXPORT_VERSION);
+        let header = preview.header.unwrap();
+        assert!(!header.public_only);
+        assert_eq!(header.kdf.iterations, EXPORT_PBKDF2_ITERATIONS);
+        assert_eq!(header.manifest.len(), 2);
+        assert_eq!(header.manifest[0].name, "work");
+        assert!(header.manifest[0].has_private_key);
+        assert_eq!(header.manifest[1].fingerprint, key_fingerprint("age1alicepublickey"));
+        assert!(!header.manifest[1].has_private_key);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn tampered_export_header_fails_to_decrypt() {
+        let dir = temp_dir("export");
+        let path = dir.join("keys.taki").to_string_lossy().to_string();
+        export_keys_to_file("passphrase", &export_test_keys(), &path, &ExportOptions::default()).unwrap();
+
+        let data = fs::read(&path).unwrap();
+        let mut header = preview_export_file(&path).unwrap().header.unwrap();
+        header.manifest[0].has_private_key = false;
+        header.public_only = true;
+        fs::write(&path, replace_export_header(&data, &header)).unwrap();
+
+        assert!(preview_export_file(&path).unwrap().header.unwrap().public_only);
+        assert!(import_keys_from_file("passphrase", &path).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_out_of_range_iteration_counts() {
+        let dir = temp_dir("export");
+        let path = dir.join("keys.taki").to_string_lossy().to_string();
+        export_keys_to_file("passphrase", &export_test_keys(), &path, &ExportOptions::default()).unwrap();
+
+        let data = fs::read(&path).unwrap();
+        let header = preview_export_file(&path).unwrap().header.unwrap();
+        for iterations in [1, EXPORT_MIN_ITERATIONS - 1, EXPORT_MAX_ITERATIONS + 1, u32::MAX] {
+            let mut tampered = header.clone();
+            tampered.kdf.iterations = iterations;
+            fs::write(&path, replace_export_header(&data, &tampered)).unwrap();
+
+            let error = import_keys_from_file("passphrase", &path).unwrap_err();
+            assert!(error.contains("iteration count"), "{}", error);
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -0,0 +1,289 @@
+use crate::age_store::{is_age_data, is_armored_age_data, try_decrypt_age_data, AgeDecryptFailure};
+use crate::key_storage::{
+    decrypt_legacy_container, list_key_storage_backups, restore_key_storage_backup, KeyStorage, StoredKey,
+    EXPORT_MAGIC, LEGACY_NONCE_LEN, LEGACY_TAG_LEN, STORAGE_VERSION,
+};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// Smallest legacy container that can hold any key storage: nonce, tag and "{}"
+const LEGACY_MIN_LEN: usize = LEGACY_NONCE_LEN + LEGACY_TAG_LEN + 2;
+
+/// Container format of a key storage file
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum StorageFileFormat {
+    Legacy,
+    Age,
+    Unknown,
+}
+
+/// Outcome of checking a key storage file, in the order the checks run
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum StorageHealth {
+    Healthy,
+    Missing,
+    Empty,
+    /// Too short to be a complete container
+    Truncated,
+    /// Not a key store container at all
+    UnknownFormat,
+    /// Malformed or truncated age header
+    InvalidHeader,
+    /// Wrong passphrase or identity, or the ciphertext was modified. The
+    /// cipher cannot tell these apart.
+    AuthenticationFailed,
+    /// The age payload is truncated or was modified after the header
+    CorruptedPayload,
+    /// Decrypted, but the content is not valid JSON
+    InvalidJson,
+    /// Valid JSON that does not match the key storage structure
+    SchemaMismatch,
+    /// Written by a newer TauriAge version
+    UnsupportedVersion,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageDiagnosis {
+    pub path: String,
+    pub size: u64,
+    pub format: StorageFileFormat,
+    pub health: StorageHealth,
+    pub message: String,
+    pub version: Option<u32>,
+    pub generation: Option<u64>,
+    pub key_count: Option<usize>,
+    /// Keys that can be recovered from a decrypted but damaged store
+    pub salvageable_keys: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyStorageReport {
+    pub file: StorageDiagnosis,
+    /// Backups, newest first
+    pub backups: Vec<StorageDiagnosis>,
+    pub latest_healthy_backup: Option<String>,
+}
+
+fn diagnosis(path: &str, size: u64, format: StorageFileFormat, health: StorageHealth, message: String) -> StorageDiagnosis {
+    StorageDiagnosis {
+        path: path.to_string(),
+        size,
+        format,
+        health,
+        message,
+        version: None,
+        generation: None,
+        key_count: None,
+        salvageable_keys: None,
+    }
+}
+
+/// Decrypt a key storage container, classifying the failure when it does not open
+fn decrypt_container(secret: &str, data: &[u8]) -> Result<(StorageFileFormat, Vec<u8>), (StorageFileFormat, StorageHealth, String)> {
+    if is_age_data(data) {
+        return try_decrypt_age_data(secret, data)
+            .map(|plaintext| (StorageFileFormat::Age, plaintext))
+            .map_err(|e| {
+                let health = match e {
+                    AgeDecryptFailure::InvalidHeader(_) => StorageHealth::InvalidHeader,
+                    AgeDecryptFailure::Unlock(_) => StorageHealth::AuthenticationFailed,
+                    AgeDecryptFailure::Payload(_) => StorageHealth::CorruptedPayload,
+                };
+                (StorageFileFormat::Age, health, e.to_string())
+            });
+    }
+
+    let unknown = |message: &str| Err((StorageFileFormat::Unknown, StorageHealth::UnknownFormat, message.to_string()));
+    if data.starts_with(EXPORT_MAGIC) {
+        return unknown("This is a key export file, not a key store. Import it instead.");
+    }
+    if is_armored_age_data(data) {
+        return unknown("ASCII-armored age files are not supported as key stores. Decrypt it with `age -d` and import the keys.");
+    }
+    if data.trim_ascii_start().starts_with(b"{") && serde_json::from_slice::<serde_json::Value>(data).is_ok() {
+        return unknown("The file is unencrypted JSON, not an encrypted key store");
+    }
+
+    if data.len() < LEGACY_MIN_LEN {
+        return Err((
+            StorageFileFormat::Legacy,
+            StorageHealth::Truncated,
+            format!("The file is {} bytes long, shorter than any valid key store", data.len()),
+        ));
+    }
+
+    decrypt_legacy_container(secret, data)
+        .map(|plaintext| (StorageFileFormat::Legacy, plaintext))
+        .map_err(|_| {
+            (
+                StorageFileFormat::Legacy,
+                StorageHealth::AuthenticationFailed,
+                "Authentication failed: the passphrase is wrong, or the file was modified or truncated".to_string(),
+            )
+        })
+}
+
+/// Check decrypted key storage JSON against the expected structure
+fn check_contents(result: &mut StorageDiagnosis, plaintext: &[u8]) {
+    result.salvageable_keys = Some(salvage_keys_from_json(plaintext).len());
+
+    let value: serde_json::Value = match serde_json::from_slice(plaintext) {
+        Ok(value) => value,
+        Err(e) => {
+            result.health = StorageHealth::InvalidJson;
+            result.message = if e.is_eof() {
+                format!("The decrypted content ends early: {}", e)
+            } else {
+                format!("The decrypted content is not valid JSON: {}", e)
+            };
+            return;
+        }
+    };
+
+    result.version = value.get("version").and_then(|v| v.as_u64()).map(|v| v as u32);
+    result.generation = value.get("generation").and_then(|v| v.as_u64());
+
+    if let Some(version) = result.version.filter(|v| *v > STORAGE_VERSION) {
+        result.health = StorageHealth::UnsupportedVersion;
+        result.message = format!(
+            "The key store has format version {}, this version of TauriAge reads up to {}",
+            version, STORAGE_VERSION
+        );
+        return;
+    }
+
+    match serde_json::from_value::<KeyStorage>(value) {
+        Ok(storage) => {
+            result.key_count = Some(storage.keys.len());
+            result.health = StorageHealth::Healthy;
+            result.message = format!("The key store is intact and holds {} keys", storage.keys.len());
+        }
+        Err(e) => {
+            result.health = StorageHealth::SchemaMismatch;
+            result.message = format!("The key store does not have the expected structure: {}", e);
+        }
+    }
+}
+
+/// Run all checks against the key storage file or backup at `path`
+pub fn diagnose_key_storage_file(secret: &str, path: &str) -> StorageDiagnosis {
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return diagnosis(path, 0, StorageFileFormat::Unknown, StorageHealth::Missing, "The file does not exist".to_string())
+        }
+        Err(e) => {
+            return diagnosis(path, 0, StorageFileFormat::Unknown, StorageHealth::Missing, format!("Failed to read the file: {}", e))
+        }
+    };
+    let size = data.len() as u64;
+
+    if data.is_empty() {
+        return diagnosis(path, size, StorageFileFormat::Unknown, StorageHealth::Empty, "The file is empty".to_string());
+    }
+
+    match decrypt_container(secret, &data) {
+        Ok((format, plaintext)) => {
+            let mut result = diagnosis(path, size, format, StorageHealth::Healthy, String::new());
+            check_contents(&mut result, &plaintext);
+            result
+        }
+        Err((format, health, message)) => diagnosis(path, size, format, health, message),
+    }
+}
+
+/// Check a key storage file and all of its backups
+pub fn check_key_storage(secret: &str, file_path: &str) -> Result<KeyStorageReport, String> {
+    let file = diagnose_key_storage_file(secret, file_path);
+    let backups: Vec<StorageDiagnosis> = list_key_storage_backups(file_path)?
+        .iter()
+        .map(|backup| diagnose_key_storage_file(secret, &backup.path))
+        .collect();
+    let latest_healthy_backup = backups
+        .iter()
+        .find(|b| b.health == StorageHealth::Healthy)
+        .map(|b| b.path.clone());
+
+    Ok(KeyStorageReport {
+        file,
+        backups,
+        latest_healthy_backup,
+    })
+}
+
+/// Replace a damaged key storage file with its newest backup that passes all
+/// checks. Returns the diagnosis of the restored backup.
+pub fn recover_key_storage_from_backup(secret: &str, file_path: &str) -> Result<StorageDiagnosis, String> {
+    let report = check_key_storage(secret, file_path)?;
+    if report.file.health == StorageHealth::Healthy {
+        return Err("The key store is intact, there is nothing to recover".to_string());
+    }
+
+    let backup = report
+        .backups
+        .into_iter()
+        .find(|b| b.health == StorageHealth::Healthy)
+        .ok_or("No backup could be opened with this passphrase or identity")?;
+    restore_key_storage_backup(file_path, &backup.path)?;
+    Ok(backup)
+}
+
+/// Keys that deserialize on their own from decrypted key storage JSON.
+/// Intact JSON is walked through its `keys` array; broken JSON is scanned for
+/// key objects, since the serializer writes each key as `{"id":...}`.
+pub fn salvage_keys_from_json(plaintext: &[u8]) -> Vec<StoredKey> {
+    let mut keys: Vec<StoredKey> = match serde_json::from_slice::<serde_json::Value>(plaintext) {
+        Ok(value) => value
+            .get("keys")
+            .and_then(|keys| keys.as_array())
+            .map(|keys| {
+                keys.iter()
+                    .filter_map(|key| serde_json::from_value(key.clone()).ok())
+                    .collect()
+            })
+            .unwrap_or_default(),
+        Err(_) => {
+            // Keys come first; stop before contacts, whose objects also start with an id
+            let end = find(plaintext, b"\"contacts\":").unwrap_or(plaintext.len());
+            let region = &plaintext[..end];
+
+            let mut found = Vec::new();
+            let mut pos = 0;
+            while let Some(offset) = find(&region[pos..], b"{\"id\":") {
+                let start = pos + offset;
+                let mut stream = serde_json::Deserializer::from_slice(&region[start..]).into_iter::<StoredKey>();
+                if let Some(Ok(key)) = stream.next() {
+                    found.push(key);
+                }
+                pos = start + 1;
+            }
+            found
+        }
+    };
+
+    let mut seen = std::collections::HashSet::new();
+    keys.retain(|key| seen.insert(key.public_key.trim().to_string()));
+    keys
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// Decrypt a key storage file or backup and return the keys that can still
+/// be read, for stores that open but no longer parse as a whole
+pub fn salvage_keys(secret: &str, path: &str) -> Result<Vec<StoredKey>, String> {
+    let data = fs::read(Path::new(path)).map_err(|e| format!("Failed to read key storage file: {}", e))?;
+    let (_, plaintext) = decrypt_container(secret, &data).map_err(|(_, _, message)| message)?;
+
+    let keys = salvage_keys_from_json(&plaintext);
+    if keys.is_empty() {
+        return Err("No keys could be recovered from this file".to_string());
+    }
+    Ok(keys)
+}
@@ -1,36 +1,124 @@
 mod age;
+mod age_store;
+mod cli;
 mod commands;
+mod contacts;
+mod envelope;
+mod file_detection;
+mod fs_util;
+mod import_plan;
+mod key_files;
 mod key_storage;
+mod key_storage_check;
+mod open_files;
+mod output_paths;
+mod profiles;
+mod secure_delete;
+mod settings;
+mod ssh_keys;
+mod verify;
+
+/// Run the command-line interface when the process was started with a
+/// subcommand. Returns the exit code, or None to open the window instead.
+pub fn run_cli() -> Option<i32> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    cli::is_cli_invocation(&args).then(|| cli::run(&args))
+}
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-    tauri::Builder::default()
+    let cwd = std::env::current_dir().unwrap_or_default();
+    let launch_requests = open_files::parse_open_files_args(std::env::args().skip(1), &cwd);
+
+    let builder = tauri::Builder::default();
+
+    // A second launch, e.g. double-clicking another .age file, hands its
+    // arguments to the running instance and exits
+    #[cfg(desktop)]
+    let builder = builder.plugin(tauri_plugin_single_instance::init(|app, args, cwd| {
+        let requests = open_files::parse_open_files_args(args.iter().skip(1), std::path::Path::new(&cwd));
+        open_files::deliver_open_files(app, requests);
+    }));
+
+    builder
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_clipboard_manager::init())
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_shell::init())
-        .manage(commands::KeyStore {
-            keys: std::sync::Mutex::new(Vec::new()),
+        .manage(commands::KeyStore::default())
+        .manage(open_files::PendingOpenFiles {
+            requests: std::sync::Mutex::new(launch_requests),
         })
         .invoke_handler(tauri::generate_handler![
             commands::generate_age_keys,
             commands::encrypt_file_cmd,
             commands::decrypt_file_cmd,
+            commands::suggest_output_path_cmd,
+            commands::encrypt_envelope_cmd,
+            commands::decrypt_envelope_cmd,
             commands::derive_public_key_from_ssh,
             commands::paste_ssh_key_from_clipboard,
             commands::get_default_key_storage_path_cmd,
             commands::key_storage_exists_cmd,
             commands::load_key_storage_cmd,
             commands::save_key_storage_cmd,
+            commands::list_key_storage_backups_cmd,
+            commands::restore_key_storage_backup_cmd,
+            commands::check_key_storage_cmd,
+            commands::recover_key_storage_cmd,
+            commands::salvage_key_storage_keys_cmd,
+            commands::get_key_storage_encryption_cmd,
+            commands::set_key_storage_encryption_cmd,
             commands::create_stored_key_cmd,
             commands::get_or_create_passphrase_cmd,
             commands::export_keys_cmd,
             commands::import_keys_cmd,
+            commands::preview_export_file_cmd,
+            commands::plan_key_import_cmd,
+            commands::apply_key_import_cmd,
+            commands::import_age_key_files_cmd,
+            commands::export_identity_file_cmd,
+            commands::export_recipients_file_cmd,
+            commands::export_public_key_bundle_cmd,
+            commands::scan_ssh_keys_cmd,
+            commands::import_ssh_keys_cmd,
+            commands::list_contacts_cmd,
+            commands::create_contact_cmd,
+            commands::save_contact_cmd,
+            commands::delete_contact_cmd,
+            commands::add_contact_key_cmd,
+            commands::accept_contact_key_cmd,
+            commands::reject_contact_key_cmd,
+            commands::list_recipient_groups_cmd,
+            commands::create_recipient_group_cmd,
+            commands::save_recipient_group_cmd,
+            commands::delete_recipient_group_cmd,
+            commands::expand_recipient_groups_cmd,
+            commands::get_storage_location_cmd,
+            commands::list_profiles_cmd,
+            commands::get_active_profile_cmd,
+            commands::create_profile_cmd,
+            commands::rename_profile_cmd,
+            commands::set_profile_passphrase_mode_cmd,
+            commands::switch_profile_cmd,
+            commands::delete_profile_cmd,
+            commands::get_settings_cmd,
+            commands::set_settings_cmd,
+            commands::reset_settings_cmd,
             commands::get_user_home_directory,
             commands::get_platform,
-            commands::list_directory_contents
+            commands::list_directory_contents,
+            commands::take_pending_open_files_cmd
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|_app, _event| {
+            // macOS delivers files opened through the file association as an event
+            #[cfg(any(target_os = "macos", target_os = "ios"))]
+            if let tauri::RunEvent::Opened { urls } = _event {
+                let paths = urls.into_iter().filter_map(|url| url.to_file_path().ok());
+                open_files::deliver_open_files(_app, open_files::open_files_requests(paths));
+            }
+        });
 }
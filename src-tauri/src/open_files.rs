@@ -0,0 +1,112 @@
+use crate::file_detection::detect_file_kind;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Event emitted to the frontend when files are opened while the app is running
+pub const OPEN_FILES_EVENT: &str = "open-files";
+
+/// What the frontend should offer to do with files the app was asked to open
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", tag = "action")]
+pub enum OpenFilesRequest {
+    Decrypt { files: Vec<String> },
+    Encrypt { files: Vec<String> },
+}
+
+/// Requests received before the frontend was ready to listen for events
+#[derive(Default)]
+pub struct PendingOpenFiles {
+    pub requests: Mutex<Vec<OpenFilesRequest>>,
+}
+
+impl PendingOpenFiles {
+    pub fn push(&self, requests: Vec<OpenFilesRequest>) {
+        if let Ok(mut pending) = self.requests.lock() {
+            pending.extend(requests);
+        }
+    }
+
+    pub fn take(&self) -> Vec<OpenFilesRequest> {
+        self.requests
+            .lock()
+            .map(|mut pending| std::mem::take(&mut *pending))
+            .unwrap_or_default()
+    }
+}
+
+/// Queue requests for the frontend and tell it to fetch them. Requests are
+/// queued rather than sent with the event, so that files opened before the
+/// frontend listens are not lost.
+pub fn deliver_open_files(app: &tauri::AppHandle, requests: Vec<OpenFilesRequest>) {
+    use tauri::{Emitter, Manager};
+
+    if requests.is_empty() {
+        return;
+    }
+
+    app.state::<PendingOpenFiles>().push(requests);
+    let _ = app.emit(OPEN_FILES_EVENT, ());
+
+    if let Some(window) = app.get_webview_window("main") {
+        let _ = window.unminimize();
+        let _ = window.set_focus();
+    }
+}
+
+/// Whether a file is an age file, judged by its header rather than its extension
+pub fn is_age_file(path: &Path) -> bool {
+    detect_file_kind(path).is_age()
+}
+
+/// Turn launch arguments into open requests. Options (including the `-psn_`
+/// argument macOS adds) are skipped, relative paths are resolved against
+/// `cwd`, and only existing files are kept. age files are offered for
+/// decryption, all other files for encryption.
+pub fn parse_open_files_args<I, S>(args: I, cwd: &Path) -> Vec<OpenFilesRequest>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let paths = args
+        .into_iter()
+        .filter(|arg| !arg.as_ref().starts_with('-'))
+        .map(|arg| resolve_path(arg.as_ref(), cwd));
+
+    open_files_requests(paths)
+}
+
+/// Classify already resolved paths, e.g. from a macOS "open document" event
+pub fn open_files_requests<I>(paths: I) -> Vec<OpenFilesRequest>
+where
+    I: IntoIterator<Item = PathBuf>,
+{
+    let mut decrypt = Vec::new();
+    let mut encrypt = Vec::new();
+
+    for path in paths.into_iter().filter(|p| p.is_file()) {
+        let list = if is_age_file(&path) { &mut decrypt } else { &mut encrypt };
+        let path = path.to_string_lossy().to_string();
+        if !list.contains(&path) {
+            list.push(path);
+        }
+    }
+
+    let mut requests = Vec::new();
+    if !decrypt.is_empty() {
+        requests.push(OpenFilesRequest::Decrypt { files: decrypt });
+    }
+    if !encrypt.is_empty() {
+        requests.push(OpenFilesRequest::Encrypt { files: encrypt });
+    }
+    requests
+}
+
+fn resolve_path(arg: &str, cwd: &Path) -> PathBuf {
+    let path = Path::new(arg);
+    if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        cwd.join(path)
+    }
+}
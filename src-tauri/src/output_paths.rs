@@ -0,0 +1,202 @@
+use crate::settings::{OutputDirectoryPolicy, OverwritePolicy, Settings};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+const AGE_EXTENSION: &str = ".age";
+const ARMORED_EXTENSION: &str = ".age.asc";
+const DECRYPTED_EXTENSION: &str = ".decrypted";
+
+/// Highest " (n)" suffix tried before giving up on finding a free name
+const MAX_SUFFIX: u32 = 9999;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum FileOperation {
+    Encrypt,
+    Decrypt,
+}
+
+/// Default output file name: `<name>.age` (`<name>.age.asc` when armored) for
+/// encryption, and the name without `.age`/`.age.asc` for decryption. Files
+/// without an age extension decrypt to `<name>.decrypted`.
+pub fn default_output_name(input_name: &str, operation: FileOperation, armor: bool) -> String {
+    match operation {
+        FileOperation::Encrypt if armor => format!("{}{}", input_name, ARMORED_EXTENSION),
+        FileOperation::Encrypt => format!("{}{}", input_name, AGE_EXTENSION),
+        FileOperation::Decrypt => strip_age_extension(input_name)
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("{}{}", input_name, DECRYPTED_EXTENSION)),
+    }
+}
+
+fn strip_age_extension(name: &str) -> Option<&str> {
+    [ARMORED_EXTENSION, AGE_EXTENSION]
+        .iter()
+        .find_map(|ext| strip_suffix_ignore_case(name, ext))
+        .filter(|stem| !stem.is_empty())
+}
+
+fn strip_suffix_ignore_case<'a>(name: &'a str, suffix: &str) -> Option<&'a str> {
+    let split = name.len().checked_sub(suffix.len())?;
+    let (stem, tail) = (name.get(..split)?, name.get(split..)?);
+    tail.eq_ignore_ascii_case(suffix).then_some(stem)
+}
+
+/// Output path for an input file under the output directory policy of the
+/// settings. With `OutputDirectoryPolicy::Ask` this is only the suggestion
+/// shown in the file dialog, next to the input.
+pub fn default_output_path(input: &Path, operation: FileOperation, armor: bool, settings: &Settings) -> PathBuf {
+    let input_name = input
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let name = default_output_name(&input_name, operation, armor);
+
+    match &settings.output_directory {
+        OutputDirectoryPolicy::Fixed { path } => Path::new(path).join(name),
+        OutputDirectoryPolicy::SameAsInput | OutputDirectoryPolicy::Ask => input.with_file_name(name),
+    }
+}
+
+/// Split a file name before its age extension, or before its last extension,
+/// so that a counter lands where users expect it: "report (1).pdf.age"
+fn split_for_suffix(name: &str) -> (&str, &str) {
+    for ext in [ARMORED_EXTENSION, AGE_EXTENSION] {
+        if let Some(stem) = strip_suffix_ignore_case(name, ext).filter(|s| !s.is_empty()) {
+            // Keep the original extension of the encrypted file together with the stem
+            let (inner_stem, _) = split_for_suffix(stem);
+            return (inner_stem, &name[inner_stem.len()..]);
+        }
+    }
+
+    match name.rfind('.') {
+        Some(dot) if dot > 0 => name.split_at(dot),
+        _ => (name, ""),
+    }
+}
+
+/// First "name (n).ext" that does not exist yet
+fn next_free_path(path: &Path) -> Result<PathBuf, String> {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .ok_or("Output path has no file name")?;
+    let (stem, ext) = split_for_suffix(&name);
+
+    (1..=MAX_SUFFIX)
+        .map(|n| path.with_file_name(format!("{} ({}){}", stem, n, ext)))
+        .find(|candidate| !candidate.exists())
+        .ok_or(format!("Could not find a free file name for {}", path.display()))
+}
+
+/// Whether two paths name the same file, following symlinks and hard links
+pub fn is_same_file(a: &Path, b: &Path) -> bool {
+    let (Ok(a_meta), Ok(b_meta)) = (std::fs::metadata(a), std::fs::metadata(b)) else {
+        return false;
+    };
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        a_meta.dev() == b_meta.dev() && a_meta.ino() == b_meta.ino()
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (a_meta, b_meta);
+        match (a.canonicalize(), b.canonicalize()) {
+            (Ok(a), Ok(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+/// Check an output path against its input and the overwrite policy, returning
+/// the path to actually write to
+pub fn resolve_output_path(input: &Path, output: &Path, policy: OverwritePolicy) -> Result<PathBuf, String> {
+    if output.as_os_str().is_empty() {
+        return Err("No output file specified".to_string());
+    }
+    if input == output || is_same_file(input, output) {
+        return Err(format!(
+            "The output file {} is the input file. Choose a different output name.",
+            output.display()
+        ));
+    }
+    if output.is_dir() {
+        return Err(format!("The output path {} is a directory", output.display()));
+    }
+    if !output.exists() {
+        return Ok(output.to_path_buf());
+    }
+
+    match policy {
+        OverwritePolicy::Refuse => Err(format!(
+            "The output file {} already exists",
+            output.display()
+        )),
+        OverwritePolicy::Overwrite => Ok(output.to_path_buf()),
+        OverwritePolicy::AutoSuffix => next_free_path(output),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_before_age_extension_and_inner_extension() {
+        assert_eq!(split_for_suffix("report.pdf.age"), ("report", ".pdf.age"));
+        assert_eq!(split_for_suffix("report.pdf.age.asc"), ("report", ".pdf.age.asc"));
+        assert_eq!(split_for_suffix("Report.PDF.AGE"), ("Report", ".PDF.AGE"));
+        assert_eq!(split_for_suffix("archive.tar.gz"), ("archive.tar", ".gz"));
+    }
+
+    #[test]
+    fn splits_dotfiles_and_names_without_extension() {
+        assert_eq!(split_for_suffix("README"), ("README", ""));
+        assert_eq!(split_for_suffix(".bashrc"), (".bashrc", ""));
+        assert_eq!(split_for_suffix(".bashrc.age"), (".bashrc", ".age"));
+        assert_eq!(split_for_suffix(".age"), (".age", ""));
+        assert_eq!(split_for_suffix("notes.age"), ("notes", ".age"));
+    }
+
+    #[test]
+    fn strips_suffix_ignoring_case_only_on_char_boundaries() {
+        assert_eq!(strip_suffix_ignore_case("file.AGE", ".age"), Some("file"));
+        assert_eq!(strip_suffix_ignore_case("file.txt", ".age"), None);
+        assert_eq!(strip_suffix_ignore_case("ag", ".age"), None);
+        assert_eq!(strip_suffix_ignore_case("fileé.age", ".age"), Some("fileé"));
+        assert_eq!(strip_suffix_ignore_case("éé", ".age"), None);
+    }
+
+    #[test]
+    fn default_names() {
+        assert_eq!(default_output_name("report.pdf", FileOperation::Encrypt, false), "report.pdf.age");
+        assert_eq!(default_output_name("report.pdf", FileOperation::Encrypt, true), "report.pdf.age.asc");
+        assert_eq!(default_output_name("report.pdf.age", FileOperation::Decrypt, false), "report.pdf");
+        assert_eq!(default_output_name("report.pdf.AGE.ASC", FileOperation::Decrypt, false), "report.pdf");
+        assert_eq!(default_output_name("report.bin", FileOperation::Decrypt, false), "report.bin.decrypted");
+        assert_eq!(default_output_name(".age", FileOperation::Decrypt, false), ".age.decrypted");
+    }
+
+    #[test]
+    fn next_free_path_counts_up() {
+        let dir = std::env::temp_dir().join(format!("tauriage-output-paths-{}", uuid::Uuid::new_v4().simple()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let taken = dir.join("report.pdf.age");
+        std::fs::write(&taken, b"").unwrap();
+        assert_eq!(next_free_path(&taken).unwrap(), dir.join("report (1).pdf.age"));
+
+        std::fs::write(dir.join("report (1).pdf.age"), b"").unwrap();
+        assert_eq!(next_free_path(&taken).unwrap(), dir.join("report (2).pdf.age"));
+        assert_eq!(next_free_path(&dir.join("README")).unwrap(), dir.join("README (1)"));
+
+        let resolved = resolve_output_path(&dir.join("report.pdf"), &taken, OverwritePolicy::AutoSuffix).unwrap();
+        assert_eq!(resolved, dir.join("report (2).pdf.age"));
+        assert!(resolve_output_path(&dir.join("report.pdf"), &taken, OverwritePolicy::Refuse).is_err());
+        assert!(resolve_output_path(&taken, &taken, OverwritePolicy::Overwrite).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,257 @@
+use crate::fs_util::{commit_temp_file, temp_path_for, write_atomic, write_private_file};
+use crate::key_storage::{
+    change_key_storage_passphrase, current_timestamp, generate_auto_passphrase, get_app_config_dir,
+    get_profile_key_storage_path, get_profile_passphrase_file_path,
+};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+/// Id of the profile that uses the original, pre-profiles storage location
+pub const DEFAULT_PROFILE_ID: &str = "default";
+
+const PROFILES_FILE: &str = "profiles.json";
+const PROFILES_DIR: &str = "profiles";
+const PROFILES_VERSION: u32 = 1;
+
+/// How the key storage of a profile is unlocked
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum PassphraseMode {
+    /// Generated passphrase kept in the profile's `.passphrase` file
+    #[default]
+    Auto,
+    /// Passphrase entered by the user on every unlock
+    Manual,
+}
+
+/// A named vault with its own key storage, passphrase and settings
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Profile {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub passphrase_mode: PassphraseMode,
+    pub created_at: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileRegistry {
+    pub version: u32,
+    pub active: String,
+    pub profiles: Vec<Profile>,
+}
+
+impl Default for ProfileRegistry {
+    fn default() -> Self {
+        ProfileRegistry {
+            version: PROFILES_VERSION,
+            active: DEFAULT_PROFILE_ID.to_string(),
+            profiles: vec![Profile {
+                id: DEFAULT_PROFILE_ID.to_string(),
+                name: "Default".to_string(),
+                passphrase_mode: PassphraseMode::Auto,
+                created_at: current_timestamp(),
+            }],
+        }
+    }
+}
+
+impl ProfileRegistry {
+    fn find(&self, id: &str) -> Result<&Profile, String> {
+        self.profiles
+            .iter()
+            .find(|p| p.id == id)
+            .ok_or(format!("Profile not found: {}", id))
+    }
+
+    fn check_name_available(&self, name: &str, except_id: Option<&str>) -> Result<(), String> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("Profile name cannot be empty".to_string());
+        }
+        let taken = self
+            .profiles
+            .iter()
+            .any(|p| Some(p.id.as_str()) != except_id && p.name.eq_ignore_ascii_case(name));
+        if taken {
+            return Err(format!("A profile named \"{}\" already exists", name));
+        }
+        Ok(())
+    }
+}
+
+fn profiles_file_path() -> Result<PathBuf, String> {
+    Ok(get_app_config_dir()?.join(PROFILES_FILE))
+}
+
+/// Load the profile registry, falling back to the single default profile
+/// when no profiles have been created yet
+pub fn load_profiles() -> Result<ProfileRegistry, String> {
+    let path = profiles_file_path()?;
+    if !path.exists() {
+        return Ok(ProfileRegistry::default());
+    }
+
+    let data = fs::read(&path).map_err(|e| format!("Failed to read profiles file: {}", e))?;
+    let mut registry: ProfileRegistry = serde_json::from_slice(&data)
+        .map_err(|e| format!("Failed to parse profiles file: {}", e))?;
+
+    if !registry.profiles.iter().any(|p| p.id == DEFAULT_PROFILE_ID) {
+        registry.profiles.insert(0, ProfileRegistry::default().profiles.remove(0));
+    }
+    if registry.find(&registry.active).is_err() {
+        registry.active = DEFAULT_PROFILE_ID.to_string();
+    }
+
+    Ok(registry)
+}
+
+fn save_profiles(registry: &ProfileRegistry) -> Result<(), String> {
+    let json = serde_json::to_vec_pretty(registry)
+        .map_err(|e| format!("Failed to serialize profiles: {}", e))?;
+    write_atomic(&profiles_file_path()?, &json)
+        .map_err(|e| format!("Failed to write profiles file: {}", e))
+}
+
+/// Directory holding the key storage, passphrase and settings of a profile.
+/// The default profile lives directly in the app config directory, where
+/// key storage was kept before profiles existed.
+pub fn get_profile_dir(profile_id: &str) -> Result<PathBuf, String> {
+    let config_dir = get_app_config_dir()?;
+    let dir = if profile_id == DEFAULT_PROFILE_ID {
+        config_dir
+    } else {
+        config_dir.join(PROFILES_DIR).join(profile_id)
+    };
+
+    fs::create_dir_all(&dir).map_err(|e| format!("Could not create profile directory: {}", e))?;
+    Ok(dir)
+}
+
+/// Directory of the active profile
+pub fn get_active_profile_dir() -> Result<PathBuf, String> {
+    get_profile_dir(&load_profiles()?.active)
+}
+
+pub fn get_active_profile() -> Result<Profile, String> {
+    let registry = load_profiles()?;
+    registry.find(&registry.active).cloned()
+}
+
+pub fn create_profile(name: &str, passphrase_mode: PassphraseMode) -> Result<Profile, String> {
+    let mut registry = load_profiles()?;
+    registry.check_name_available(name, None)?;
+
+    let profile = Profile {
+        id: uuid::Uuid::new_v4().to_string(),
+        name: name.trim().to_string(),
+        passphrase_mode,
+        created_at: current_timestamp(),
+    };
+    get_profile_dir(&profile.id)?;
+
+    registry.profiles.push(profile.clone());
+    save_profiles(&registry)?;
+    Ok(profile)
+}
+
+pub fn rename_profile(profile_id: &str, name: &str) -> Result<Profile, String> {
+    let mut registry = load_profiles()?;
+    registry.find(profile_id)?;
+    registry.check_name_available(name, Some(profile_id))?;
+
+    let profile = registry
+        .profiles
+        .iter_mut()
+        .find(|p| p.id == profile_id)
+        .ok_or(format!("Profile not found: {}", profile_id))?;
+    profile.name = name.trim().to_string();
+    let profile = profile.clone();
+
+    save_profiles(&registry)?;
+    Ok(profile)
+}
+
+/// Change how the key storage of a profile is unlocked. The store is first
+/// re-encrypted from `current_secret` to the new passphrase: a generated one
+/// kept in `.passphrase` for Auto, or `new_passphrase` for Manual, which
+/// removes `.passphrase`. Returns the profile and the new passphrase.
+pub fn set_profile_passphrase_mode(
+    profile_id: &str,
+    passphrase_mode: PassphraseMode,
+    current_secret: &str,
+    new_passphrase: Option<&str>,
+) -> Result<(Profile, String), String> {
+    let mut registry = load_profiles()?;
+    registry.find(profile_id)?;
+
+    let passphrase = match passphrase_mode {
+        PassphraseMode::Auto => generate_auto_passphrase()?,
+        PassphraseMode::Manual => new_passphrase
+            .ok_or("Enter the passphrase that will unlock this profile")?
+            .to_string(),
+    };
+    let store_path = get_profile_key_storage_path(profile_id)?;
+    change_key_storage_passphrase(&store_path, current_secret, &passphrase)?;
+
+    let passphrase_file = PathBuf::from(get_profile_passphrase_file_path(profile_id)?);
+    let stored = match passphrase_mode {
+        PassphraseMode::Auto => {
+            let temp_path = temp_path_for(&passphrase_file);
+            write_private_file(&temp_path, passphrase.as_bytes())
+                .and_then(|_| commit_temp_file(&temp_path, &passphrase_file))
+                .map_err(|e| format!("Failed to write passphrase file: {}", e))
+        }
+        PassphraseMode::Manual if passphrase_file.exists() => fs::remove_file(&passphrase_file)
+            .map_err(|e| format!("Failed to remove passphrase file: {}", e)),
+        PassphraseMode::Manual => Ok(()),
+    };
+    if let Err(e) = stored {
+        // Keep the store openable the way it was before
+        change_key_storage_passphrase(&store_path, &passphrase, current_secret)?;
+        return Err(e);
+    }
+
+    let profile = registry
+        .profiles
+        .iter_mut()
+        .find(|p| p.id == profile_id)
+        .ok_or(format!("Profile not found: {}", profile_id))?;
+    profile.passphrase_mode = passphrase_mode;
+    let profile = profile.clone();
+
+    save_profiles(&registry)?;
+    Ok((profile, passphrase))
+}
+
+pub fn switch_profile(profile_id: &str) -> Result<Profile, String> {
+    let mut registry = load_profiles()?;
+    let profile = registry.find(profile_id)?.clone();
+
+    registry.active = profile.id.clone();
+    save_profiles(&registry)?;
+    Ok(profile)
+}
+
+/// Delete a profile together with its key storage. The active profile and
+/// the default profile cannot be deleted.
+pub fn delete_profile(profile_id: &str) -> Result<(), String> {
+    let mut registry = load_profiles()?;
+    registry.find(profile_id)?;
+
+    if profile_id == DEFAULT_PROFILE_ID {
+        return Err("The default profile cannot be deleted".to_string());
+    }
+    if registry.active == profile_id {
+        return Err("Switch to another profile before deleting this one".to_string());
+    }
+
+    let dir = get_profile_dir(profile_id)?;
+    registry.profiles.retain(|p| p.id != profile_id);
+    save_profiles(&registry)?;
+
+    fs::remove_dir_all(&dir).map_err(|e| format!("Failed to delete profile directory: {}", e))
+}
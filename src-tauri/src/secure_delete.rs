@@ -0,0 +1,141 @@
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Filesystems that write modified blocks to new locations, leaving the old
+/// contents on disk until they are reused
+#[cfg(target_os = "linux")]
+const COPY_ON_WRITE_FILESYSTEMS: &[&str] = &["btrfs", "zfs", "bcachefs", "nilfs2", "f2fs"];
+
+/// What happened to a removed original file
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct DeletionReport {
+    pub path: String,
+    pub deleted: bool,
+    /// The contents were overwritten in place before the file was unlinked
+    pub overwritten: bool,
+    /// Why the old contents may still be recoverable
+    pub warnings: Vec<String>,
+}
+
+/// Filesystem type of the mount holding `path`, from /proc/mounts
+#[cfg(target_os = "linux")]
+fn filesystem_type(path: &Path) -> Option<String> {
+    let path = path.canonicalize().ok()?;
+    let mounts = fs::read_to_string("/proc/mounts").ok()?;
+
+    mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let _device = fields.next()?;
+            let mount_point = fields.next()?.replace("\\040", " ");
+            let fs_type = fields.next()?;
+            path.starts_with(&mount_point).then(|| (mount_point.len(), fs_type.to_string()))
+        })
+        .max_by_key(|(len, _)| *len)
+        .map(|(_, fs_type)| fs_type)
+}
+
+/// Reasons overwriting this file in place may not destroy its old contents
+fn overwrite_caveats(path: &Path) -> Vec<String> {
+    let mut caveats = Vec::new();
+
+    #[cfg(target_os = "linux")]
+    if let Some(fs_type) = filesystem_type(path) {
+        if COPY_ON_WRITE_FILESYSTEMS.contains(&fs_type.as_str()) {
+            caveats.push(format!(
+                "The file is on a copy-on-write filesystem ({}); the overwrite went to new blocks and the original contents remain on disk until reused",
+                fs_type
+            ));
+        }
+    }
+    #[cfg(target_os = "macos")]
+    {
+        let _ = path;
+        caveats.push(
+            "macOS uses the copy-on-write APFS filesystem; the overwrite went to new blocks and the original contents remain on disk until reused"
+                .to_string(),
+        );
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    let _ = path;
+
+    caveats.push(
+        "Overwriting cannot be guaranteed on SSDs and flash drives, which remap writes internally, nor against snapshots, backups or cloud sync copies"
+            .to_string(),
+    );
+    caveats
+}
+
+/// Overwrite the file contents with random data and flush them to disk
+fn overwrite_contents(path: &Path) -> std::io::Result<()> {
+    let mut file = fs::OpenOptions::new().write(true).open(path)?;
+    let len = file.metadata()?.len();
+
+    let mut buffer = vec![0u8; 64 * 1024];
+    let mut remaining = len;
+    file.seek(SeekFrom::Start(0))?;
+    while remaining > 0 {
+        let chunk = remaining.min(buffer.len() as u64) as usize;
+        rand::thread_rng().fill_bytes(&mut buffer[..chunk]);
+        file.write_all(&buffer[..chunk])?;
+        remaining -= chunk as u64;
+    }
+    file.sync_all()?;
+
+    file.set_len(0)?;
+    file.sync_all()
+}
+
+/// Number of directory entries pointing at the file
+#[cfg(unix)]
+fn hard_link_count(metadata: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    metadata.nlink()
+}
+
+#[cfg(not(unix))]
+fn hard_link_count(_metadata: &fs::Metadata) -> u64 {
+    1
+}
+
+/// Overwrite a file where possible, rename it to a random name and unlink it.
+/// The report says when the old contents may survive anyway.
+pub fn secure_delete(path: &Path) -> Result<DeletionReport, String> {
+    let metadata = fs::symlink_metadata(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    if !metadata.file_type().is_file() {
+        return Err(format!("{} is not a regular file", path.display()));
+    }
+
+    let mut report = DeletionReport {
+        path: path.to_string_lossy().to_string(),
+        ..Default::default()
+    };
+
+    if hard_link_count(&metadata) > 1 {
+        // Overwriting would destroy the data seen through the other links
+        report.warnings.push(
+            "The file has other hard links; its contents were not overwritten and remain reachable through them".to_string(),
+        );
+    } else {
+        match overwrite_contents(path) {
+            Ok(()) => {
+                report.overwritten = true;
+                report.warnings.extend(overwrite_caveats(path));
+            }
+            Err(e) => report.warnings.push(format!("The contents could not be overwritten: {}", e)),
+        }
+    }
+
+    // Hide the original name from the directory entry before unlinking
+    let hidden = path.with_file_name(uuid::Uuid::new_v4().simple().to_string());
+    let target = if fs::rename(path, &hidden).is_ok() { hidden.as_path() } else { path };
+    fs::remove_file(target).map_err(|e| format!("Failed to delete {}: {}", path.display(), e))?;
+    report.deleted = true;
+
+    Ok(report)
+}
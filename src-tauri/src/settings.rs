@@ -0,0 +1,177 @@
+use crate::fs_util::write_atomic;
+use crate::profiles::get_active_profile_dir;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+const SETTINGS_FILE: &str = "settings.json";
+const SETTINGS_VERSION: u32 = 1;
+
+/// Event emitted to the frontend with the new settings whenever they change
+pub const SETTINGS_CHANGED_EVENT: &str = "settings-changed";
+
+const MAX_CONCURRENCY: usize = 16;
+
+/// Where encrypted and decrypted files are written by default
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase", tag = "kind")]
+pub enum OutputDirectoryPolicy {
+    /// Next to the input file
+    #[default]
+    SameAsInput,
+    /// Always into the given directory
+    Fixed { path: String },
+    /// Let the user pick a location for every operation
+    Ask,
+}
+
+/// What to do when an output file already exists
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum OverwritePolicy {
+    /// Fail the operation
+    #[default]
+    Refuse,
+    /// Replace the existing file
+    Overwrite,
+    /// Pick a free name such as "report (1).pdf"
+    AutoSuffix,
+}
+
+/// Which `age` executables are used for file operations
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum AgeBackend {
+    /// The binaries shipped with TauriAge
+    #[default]
+    Bundled,
+    /// `age` and `age-keygen` found on the PATH
+    System,
+}
+
+/// User preferences of the active profile. Fields missing from an older
+/// settings file take their default value.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Settings {
+    pub version: u32,
+    pub default_armor: bool,
+    /// Public keys preselected as recipients when encrypting
+    pub default_recipients: Vec<String>,
+    /// Public keys of the user's own identities, added to every encryption
+    /// so the user can always decrypt their files
+    pub self_recipients: Vec<String>,
+    pub output_directory: OutputDirectoryPolicy,
+    pub overwrite_policy: OverwritePolicy,
+    /// Number of files processed in parallel by batch operations
+    pub concurrency: usize,
+    /// Lock the key storage after this many seconds without activity, None to never lock
+    pub auto_lock_timeout_secs: Option<u64>,
+    pub backend: AgeBackend,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            version: SETTINGS_VERSION,
+            default_armor: false,
+            default_recipients: Vec::new(),
+            self_recipients: Vec::new(),
+            output_directory: OutputDirectoryPolicy::default(),
+            overwrite_policy: OverwritePolicy::default(),
+            concurrency: 2,
+            auto_lock_timeout_secs: Some(15 * 60),
+            backend: AgeBackend::default(),
+        }
+    }
+}
+
+impl Settings {
+    fn validate(&self) -> Result<(), String> {
+        if self.concurrency == 0 || self.concurrency > MAX_CONCURRENCY {
+            return Err(format!("Concurrency must be between 1 and {}", MAX_CONCURRENCY));
+        }
+        if self.auto_lock_timeout_secs == Some(0) {
+            return Err("Auto-lock timeout must be at least one second".to_string());
+        }
+        if let OutputDirectoryPolicy::Fixed { path } = &self.output_directory {
+            if path.trim().is_empty() {
+                return Err("Output directory cannot be empty".to_string());
+            }
+        }
+        if let Some(invalid) = self
+            .self_recipients
+            .iter()
+            .find(|r| !(r.trim().starts_with("age1") || r.trim().starts_with("ssh-")))
+        {
+            return Err(format!("\"{}\" is not an age or SSH public key", invalid));
+        }
+        Ok(())
+    }
+
+    /// Add the self recipients that are not among the recipients yet
+    pub fn append_self_recipients(&self, recipients: &mut Vec<String>) {
+        for public_key in &self.self_recipients {
+            let public_key = public_key.trim();
+            if !recipients.iter().any(|r| r.trim() == public_key) {
+                recipients.push(public_key.to_string());
+            }
+        }
+    }
+}
+
+fn settings_file_path() -> Result<PathBuf, String> {
+    Ok(get_active_profile_dir()?.join(SETTINGS_FILE))
+}
+
+/// Load the settings of the active profile, or the defaults when none were saved
+pub fn load_settings() -> Result<Settings, String> {
+    let path = settings_file_path()?;
+    if !path.exists() {
+        return Ok(Settings::default());
+    }
+
+    let data = fs::read(&path).map_err(|e| format!("Failed to read settings file: {}", e))?;
+    let mut settings: Settings = serde_json::from_slice(&data)
+        .map_err(|e| format!("Failed to parse settings file: {}", e))?;
+
+    if settings.version > SETTINGS_VERSION {
+        return Err(format!(
+            "Settings were saved by a newer version of TauriAge (version {})",
+            settings.version
+        ));
+    }
+    settings.version = SETTINGS_VERSION;
+
+    Ok(settings)
+}
+
+/// Validate and save the settings of the active profile
+pub fn save_settings(settings: &Settings) -> Result<Settings, String> {
+    settings.validate()?;
+
+    let mut settings = settings.clone();
+    settings.version = SETTINGS_VERSION;
+    settings.default_recipients = settings
+        .default_recipients
+        .iter()
+        .map(|r| r.trim().to_string())
+        .filter(|r| !r.is_empty())
+        .collect();
+
+    let json = serde_json::to_vec_pretty(&settings)
+        .map_err(|e| format!("Failed to serialize settings: {}", e))?;
+    write_atomic(&settings_file_path()?, &json)
+        .map_err(|e| format!("Failed to write settings file: {}", e))?;
+
+    Ok(settings)
+}
+
+/// Restore the default settings of the active profile
+pub fn reset_settings() -> Result<Settings, String> {
+    let path = settings_file_path()?;
+    if path.exists() {
+        fs::remove_file(&path).map_err(|e| format!("Failed to remove settings file: {}", e))?;
+    }
+    Ok(Settings::default())
+}
@@ -0,0 +1,257 @@
+use crate::age::{get_age_exe_path, STDIO_PATH};
+use crate::age_store::parse_age_header;
+use crate::fs_util::{temp_path_for, write_private_file};
+use crate::key_storage::StoredKey;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Bytes read to find the recipient stanzas of an encrypted file
+const HEADER_PROBE_LEN: u64 = 64 * 1024;
+
+/// How to check an encrypted file before reporting success
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", tag = "method")]
+pub enum VerifyMethod {
+    /// Decrypt with a stored identity and compare the result with the input
+    Decrypt,
+    /// Only check the header for a stanza addressed to the recipient
+    Header { recipient: String },
+}
+
+/// Outcome of checking an encrypted file
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", tag = "status")]
+pub enum Verification {
+    /// Decrypted with a stored identity to exactly the input contents
+    Decrypted,
+    /// The header has a stanza addressed to the recipient. The contents were
+    /// not decrypted.
+    StanzaFound { recipient: String },
+    /// The file could not be checked, which says nothing about whether it is valid
+    Inconclusive { reason: String },
+    /// The file cannot be opened by the expected identity or has the wrong contents
+    Failed { reason: String },
+}
+
+impl Verification {
+    pub fn reason(&self) -> Option<&str> {
+        match self {
+            Verification::Inconclusive { reason } | Verification::Failed { reason } => Some(reason),
+            _ => None,
+        }
+    }
+}
+
+/// SHA-256 of everything a reader yields, read in chunks so large files are
+/// never held in memory
+fn hash_reader<R: Read>(mut reader: R) -> io::Result<[u8; 32]> {
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hasher.finalize().into())
+}
+
+pub fn hash_file(path: &Path) -> Result<[u8; 32], String> {
+    File::open(path)
+        .and_then(hash_reader)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+}
+
+/// Whether a stored key's public key is one of the recipients
+fn is_recipient(key: &StoredKey, recipients: &[String]) -> bool {
+    let public_key = key.public_key.trim();
+    recipients.iter().any(|r| {
+        let r = r.trim();
+        // SSH recipients may carry a comment after the key
+        r == public_key || (r.starts_with("ssh-") && r.split(' ').take(2).eq(public_key.split(' ').take(2)))
+    })
+}
+
+/// Stored keys with a private key that can open a file encrypted to `recipients`
+pub fn identities_for_recipients<'a>(keys: &'a [StoredKey], recipients: &[String]) -> Vec<&'a StoredKey> {
+    keys.iter()
+        .filter(|k| k.private_key.is_some() && is_recipient(k, recipients))
+        .collect()
+}
+
+/// Temporary identity files for the age binary, removed when dropped
+pub(crate) struct IdentityFiles(Vec<PathBuf>);
+
+impl IdentityFiles {
+    pub(crate) fn create<'a, I>(private_keys: I) -> Result<Self, String>
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let mut files = IdentityFiles(Vec::new());
+        for private_key in private_keys {
+            let path = temp_path_for(&std::env::temp_dir().join("identity"));
+            write_private_file(&path, format!("{}\n", private_key.trim()).as_bytes())
+                .map_err(|e| format!("Failed to create temp identity file: {}", e))?;
+            files.0.push(path);
+        }
+        Ok(files)
+    }
+
+    /// Add `-i` arguments for all identity files
+    pub(crate) fn add_args(&self, cmd: &mut Command) {
+        for path in &self.0 {
+            cmd.arg("-i").arg(path);
+        }
+    }
+}
+
+impl Drop for IdentityFiles {
+    fn drop(&mut self) {
+        for path in &self.0 {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// Decrypt `encrypted` with the given identities and hash the plaintext as
+/// it streams out of age, without writing it anywhere
+pub fn hash_decrypted(encrypted: &Path, identities: &[&StoredKey]) -> Result<[u8; 32], String> {
+    if identities.is_empty() {
+        return Err("No identity available to decrypt the file".to_string());
+    }
+
+    let files = IdentityFiles::create(identities.iter().filter_map(|key| key.private_key.as_deref()))?;
+    let mut cmd = Command::new(get_age_exe_path("age")?);
+    cmd.arg("-d");
+    files.add_args(&mut cmd);
+    cmd.arg(encrypted)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    let mut child = cmd.spawn().map_err(|e| format!("Failed to execute age decrypt: {}", e))?;
+    let stdout = child.stdout.take().ok_or("Failed to read age output")?;
+    let hash = hash_reader(stdout);
+    let output = child
+        .wait_with_output()
+        .map_err(|e| format!("Failed to execute age decrypt: {}", e))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "The encrypted file could not be decrypted: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    hash.map_err(|e| format!("Failed to read decrypted data: {}", e))
+}
+
+/// Check that `encrypted` decrypts, with one of the stored identities among
+/// its recipients, to exactly the contents of `original`
+fn verify_by_decrypting(encrypted: &Path, original: &Path, keys: &[StoredKey], recipients: &[String]) -> Verification {
+    let identities = identities_for_recipients(keys, recipients);
+    if identities.is_empty() {
+        return Verification::Inconclusive {
+            reason: "None of the recipients has a private key in the key store".to_string(),
+        };
+    }
+
+    let decrypted = match hash_decrypted(encrypted, &identities) {
+        Ok(hash) => hash,
+        Err(reason) => return Verification::Failed { reason },
+    };
+    match hash_file(original) {
+        Ok(hash) if hash == decrypted => Verification::Decrypted,
+        Ok(_) => Verification::Failed {
+            reason: "The encrypted file does not decrypt to the original contents".to_string(),
+        },
+        Err(reason) => Verification::Inconclusive { reason },
+    }
+}
+
+/// The 4-byte key tag age puts in SSH stanzas: the start of the SHA-256 of
+/// the SSH wire-format public key
+fn ssh_key_tag(recipient: &str) -> Option<String> {
+    use base64::{engine::general_purpose::STANDARD, engine::general_purpose::STANDARD_NO_PAD, Engine as _};
+
+    let blob = STANDARD.decode(recipient.split_whitespace().nth(1)?).ok()?;
+    Some(STANDARD_NO_PAD.encode(&Sha256::digest(&blob)[..4]))
+}
+
+/// Check the header of `encrypted` for a stanza that can belong to `recipient`.
+/// SSH stanzas carry a tag of the key they are for; X25519 stanzas are
+/// anonymous, so for X25519 recipients only their presence can be confirmed.
+fn verify_header_stanza(encrypted: &Path, recipient: &str) -> Verification {
+    let recipient = recipient.trim();
+    let mut header = Vec::new();
+    if let Err(e) = File::open(encrypted).and_then(|file| file.take(HEADER_PROBE_LEN).read_to_end(&mut header)) {
+        return Verification::Inconclusive {
+            reason: format!("Failed to read {}: {}", encrypted.display(), e),
+        };
+    }
+    let Some(info) = parse_age_header(&header) else {
+        return Verification::Failed {
+            reason: "The output does not have a valid age header".to_string(),
+        };
+    };
+
+    if recipient.starts_with("ssh-") {
+        let kind = recipient.split_whitespace().next().unwrap_or_default();
+        let Some(tag) = ssh_key_tag(recipient) else {
+            return Verification::Inconclusive {
+                reason: "The SSH recipient could not be parsed".to_string(),
+            };
+        };
+        let found = info
+            .stanzas
+            .iter()
+            .any(|s| s.kind == kind && s.args.first() == Some(&tag));
+        return if found {
+            Verification::StanzaFound {
+                recipient: recipient.to_string(),
+            }
+        } else {
+            Verification::Failed {
+                reason: "The header has no stanza for the recipient".to_string(),
+            }
+        };
+    }
+
+    if info.stanzas.iter().any(|s| s.kind == "X25519") {
+        Verification::Inconclusive {
+            reason: "The header has X25519 stanzas, but they do not identify their recipient. Decrypt to verify."
+                .to_string(),
+        }
+    } else {
+        Verification::Failed {
+            reason: "The header has no X25519 stanza".to_string(),
+        }
+    }
+}
+
+/// Check an encrypted file with the chosen method
+pub fn verify_encryption(
+    encrypted: &str,
+    original: &str,
+    method: &VerifyMethod,
+    keys: &[StoredKey],
+    recipients: &[String],
+) -> Verification {
+    if encrypted == STDIO_PATH {
+        return Verification::Inconclusive {
+            reason: "Output written to standard output cannot be checked".to_string(),
+        };
+    }
+
+    match method {
+        VerifyMethod::Header { recipient } => verify_header_stanza(Path::new(encrypted), recipient),
+        VerifyMethod::Decrypt if original == STDIO_PATH => Verification::Inconclusive {
+            reason: "Input read from standard input cannot be compared".to_string(),
+        },
+        VerifyMethod::Decrypt => verify_by_decrypting(Path::new(encrypted), Path::new(original), keys, recipients),
+    }
+}
@@ -1,5 +1,6 @@
 import React, { useState, useRef, useCallback } from 'react';
 import { invoke } from '@tauri-apps/api/core';
+import { FileKind } from '../types';
 
 export interface FileItem {
   name: string;
@@ -7,6 +8,8 @@ export interface FileItem {
   isDirectory: boolean;
   size?: number;
   modified?: string;
+  kind: FileKind;
+  isHidden: boolean;
 }
 
 export interface FileExplorerProps {
@@ -9,6 +9,9 @@ export interface EncryptionResult {
   inputFile: string;
   outputFile: string;
   publicKeys: string[];
+  warnings: string[];
+  verification?: Verification | null;
+  originalDeletion?: DeletionReport | null;
 }
 
 export interface DecryptionResult {
@@ -25,6 +28,10 @@ export interface StoredKey {
   privateKey?: string;
   comment?: string;
   createdAt: number;
+  tags: string[];
+  expiresAt?: number;
+  lastUsedAt?: number;
+  useCount: number;
 }
 
 export interface EncryptedKeyEntry {
@@ -35,3 +42,219 @@ export interface EncryptedKeyEntry {
   createdAt: number;
   encryptedPrivateKey?: string; // encrypted with passphrase
 }
+
+export type KeyTrust = 'trusted' | 'pending' | 'retired';
+
+export interface KeyHistoryEntry {
+  publicKey: string;
+  fingerprint: string;
+  event: 'added' | 'changed' | 'accepted' | 'rejected' | 'removed';
+  at: number;
+}
+
+export interface ContactKey {
+  publicKey: string;
+  label?: string;
+  addedAt: number;
+  fingerprint: string;
+  trust: KeyTrust;
+}
+
+export interface ContactKeyChange {
+  contactId: string;
+  fingerprint: string;
+  trust: KeyTrust;
+  changed: boolean;
+  previousFingerprints: string[];
+}
+
+export interface Contact {
+  id: string;
+  name: string;
+  email?: string;
+  keys: ContactKey[];
+  notes?: string;
+  createdAt: number;
+  keyHistory: KeyHistoryEntry[];
+}
+
+export interface RecipientGroup {
+  id: string;
+  name: string;
+  contactIds: string[];
+  keyIds: string[];
+  createdAt: number;
+}
+
+export interface ExportOptions {
+  keyIds?: string[];
+  publicOnly?: boolean;
+}
+
+export interface ExportManifestEntry {
+  name: string;
+  publicKey: string;
+  fingerprint: string;
+  hasPrivateKey: boolean;
+}
+
+export interface ExportPreview {
+  version: number;
+  header?: {
+    kdf: { algorithm: string; iterations: number; salt: string };
+    exporter: { app: string; version: string; platform: string };
+    createdAt: number;
+    publicOnly: boolean;
+    manifest: ExportManifestEntry[];
+  };
+}
+
+export type ImportStatus = 'new' | 'identical' | 'conflict';
+export type ImportResolution = 'skipConflicts' | 'mergeMissing' | 'preferIncoming';
+
+export type ImportConflict =
+  | { kind: 'nameDiffers'; existing: string; incoming: string }
+  | { kind: 'commentDiffers' }
+  | { kind: 'tagsDiffer' }
+  | { kind: 'privateKeyAvailable' }
+  | { kind: 'privateKeyDiffers' };
+
+export interface ImportPlanEntry {
+  incoming: StoredKey;
+  status: ImportStatus;
+  existingId?: string;
+  conflicts: ImportConflict[];
+}
+
+export interface ImportPlan {
+  entries: ImportPlanEntry[];
+  newCount: number;
+  identicalCount: number;
+  conflictCount: number;
+}
+
+export interface ImportResult {
+  keys: StoredKey[];
+  added: number;
+  updated: number;
+  unchanged: number;
+  skipped: number;
+}
+
+export type PassphraseMode = 'auto' | 'manual';
+
+export interface Profile {
+  id: string;
+  name: string;
+  passphraseMode: PassphraseMode;
+  createdAt: number;
+}
+
+export type StorageEncryption =
+  | { format: 'legacy' }
+  | { format: 'agePassphrase' }
+  | { format: 'ageRecipients'; recipients: string[] };
+
+export type StorageFileFormat = 'legacy' | 'age' | 'unknown';
+
+export type StorageHealth =
+  | 'healthy'
+  | 'missing'
+  | 'empty'
+  | 'truncated'
+  | 'unknownFormat'
+  | 'invalidHeader'
+  | 'authenticationFailed'
+  | 'corruptedPayload'
+  | 'invalidJson'
+  | 'schemaMismatch'
+  | 'unsupportedVersion';
+
+export interface StorageDiagnosis {
+  path: string;
+  size: number;
+  format: StorageFileFormat;
+  health: StorageHealth;
+  message: string;
+  version?: number;
+  generation?: number;
+  keyCount?: number;
+  salvageableKeys?: number;
+}
+
+export interface KeyStorageReport {
+  file: StorageDiagnosis;
+  backups: StorageDiagnosis[];
+  latestHealthyBackup?: string;
+}
+
+export type OutputDirectoryPolicy =
+  | { kind: 'sameAsInput' }
+  | { kind: 'fixed'; path: string }
+  | { kind: 'ask' };
+
+export type OverwritePolicy = 'refuse' | 'overwrite' | 'autoSuffix';
+
+export type AgeBackend = 'bundled' | 'system';
+
+export interface Settings {
+  version: number;
+  defaultArmor: boolean;
+  defaultRecipients: string[];
+  selfRecipients: string[];
+  outputDirectory: OutputDirectoryPolicy;
+  overwritePolicy: OverwritePolicy;
+  concurrency: number;
+  autoLockTimeoutSecs?: number | null;
+  backend: AgeBackend;
+}
+
+export type OpenFilesRequest =
+  | { action: 'decrypt'; files: string[] }
+  | { action: 'encrypt'; files: string[] };
+
+export type FileOperation = 'encrypt' | 'decrypt';
+
+export interface DeletionReport {
+  path: string;
+  deleted: boolean;
+  overwritten: boolean;
+  warnings: string[];
+}
+
+export type VerifyMethod =
+  | { method: 'decrypt' }
+  | { method: 'header'; recipient: string };
+
+export type Verification =
+  | { status: 'decrypted' }
+  | { status: 'stanzaFound'; recipient: string }
+  | { status: 'inconclusive'; reason: string }
+  | { status: 'failed'; reason: string };
+
+export interface EnvelopeMetadata {
+  name: string;
+  size: number;
+  modified?: number | null;
+  mode?: number | null;
+  sha256: string;
+}
+
+export interface EnvelopeDecryptionResult {
+  success: boolean;
+  inputFile: string;
+  outputFile: string;
+  metadata: EnvelopeMetadata;
+  warnings: string[];
+}
+
+export type FileKind =
+  | 'directory'
+  | 'ageEncrypted'
+  | 'ageArmored'
+  | 'keyExport'
+  | 'ageIdentity'
+  | 'ageRecipients'
+  | 'sshPrivateKey'
+  | 'sshPublicKey'
+  | 'other';